@@ -1,11 +1,28 @@
+use crate::browser::{BrowserOutcome, SnippetBrowser};
+use crate::editor;
 use crate::event::{AppEvent, Event, EventHandler};
+use crate::extract;
+use crate::form::{FormOutcome, PlaceholderForm};
+use crate::history::{History, HistoryEntry};
 use crate::infer::InferenceEngine;
+use crate::keymap::{Action, KeyBinding, Keymap, Mode};
+use crate::pipe::Piped;
+use crate::placeholder;
+use crate::script;
+use crate::shell::Shell;
+use crate::snippets::{self, Library, Snippet};
+use crate::terminal;
+use crate::text::TextBuffer;
+use crate::theme::Theme;
+use crate::undo::{EditKind, Snapshot, UndoStack};
 use arboard::Clipboard;
 use ratatui::{
     DefaultTerminal,
     crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
 };
-use std::process::Command;
+use std::io;
+use std::path::Path;
+use std::process::Stdio;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SafetyStatus {
@@ -14,141 +31,610 @@ pub enum SafetyStatus {
     Unsafe,
 }
 
+impl SafetyStatus {
+    /// The verdict of a safety review, which leads with Y for safe or N for unsafe.
+    pub fn from_review(review: &str) -> Self {
+        match review.trim().chars().next() {
+            Some('Y') => SafetyStatus::Safe,
+            Some('N') => SafetyStatus::Unsafe,
+            _ => SafetyStatus::Unknown,
+        }
+    }
+}
+
+/// How many lines PageUp and PageDown move the cursor.
+const PAGE_LINES: isize = 10;
+
+/// What a single line prompt is asking for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinePrompt {
+    /// A path to save the generated script to.
+    SaveScript,
+    /// The path the script was to be saved to, which already exists.
+    OverwriteScript,
+    /// A name and tags to save the command to the snippet library with.
+    SaveSnippet,
+}
+
+impl LinePrompt {
+    pub fn title(&self) -> &'static str {
+        match self {
+            LinePrompt::SaveScript => "Save script as (Enter to save, Esc to cancel)",
+            LinePrompt::OverwriteScript => {
+                "That file exists (Enter to overwrite it, or change the path, Esc to cancel)"
+            }
+            LinePrompt::SaveSnippet => {
+                "Save snippet as: name #tag #tag (Enter to save, Esc to cancel)"
+            }
+        }
+    }
+}
+
 /// Application.
 #[derive(Debug)]
 pub struct App {
     pub running: bool,
     pub focused_pane: usize,
-    pub input_text: String,
-    pub response_text: String,
+    pub input: TextBuffer,
+    pub response: TextBuffer,
     pub safety_check_text: String,
-    pub input_cursor: usize,
-    pub response_cursor: usize,
+    pub undo_stack: UndoStack,
     pub events: EventHandler,
     pub client: InferenceEngine,
     pub is_loading_completion: bool,
     pub is_loading_safety_check: bool,
     pub safety_status: SafetyStatus,
     pub dry_run: bool,
+    /// A one-off message shown in the status line until the next key press.
+    pub notice: Option<String>,
+    pub keymap: Keymap,
+    pub mode: Mode,
+    pub show_help: bool,
+    /// Whether the generated command came from the response cache.
+    pub completion_cached: bool,
+    /// The version of the prompt the command was generated with, or `None` if it didn't come
+    /// from the model.
+    pub prompt_version: Option<String>,
+    /// Whether the safety check came from the response cache.
+    pub safety_check_cached: bool,
+    /// Whether the debug pane, showing recent requests to the model, is open.
+    pub show_debug: bool,
+    /// How far the debug pane is scrolled, in lines.
+    pub debug_scroll: u16,
+    pub theme: Theme,
+    /// Generate multi-line scripts to save, rather than commands to run.
+    pub script_mode: bool,
+    /// Prose the model wrapped around the generated command.
+    pub commentary_text: String,
+    /// Results of static checks on the generated script.
+    pub script_check_text: String,
+    /// A single line prompt and what has been typed into it, while it is open.
+    pub line_prompt: Option<(LinePrompt, TextBuffer)>,
+    /// The form for filling in a command's placeholders, while it is open.
+    pub form: Option<PlaceholderForm>,
+    /// The snippet library browser, while it is open.
+    pub browser: Option<SnippetBrowser>,
+    /// Data piped into uhh, for the command to read when it runs.
+    pub piped: Option<Piped>,
+    /// The last prompt sent to the model, and whether it asked for a script. Sending the same
+    /// again asks for a new answer, rather than the cached one.
+    last_request: Option<(String, bool)>,
 }
 
 impl App {
     /// Constructs a new instance of [`App`].
-    pub fn new(client: InferenceEngine, dry_run: bool) -> Self {
+    pub fn new(
+        client: InferenceEngine,
+        dry_run: bool,
+        script_mode: bool,
+        keymap: Keymap,
+        theme: Theme,
+    ) -> Self {
         Self {
             running: true,
             focused_pane: 0,
-            input_text: String::new(),
-            response_text: String::new(),
+            input: TextBuffer::new(),
+            response: TextBuffer::new(),
             safety_check_text: String::new(),
-            input_cursor: 0,
-            response_cursor: 0,
+            undo_stack: UndoStack::new(),
             events: EventHandler::new(),
             client,
             is_loading_completion: false,
             is_loading_safety_check: false,
             safety_status: SafetyStatus::Unknown,
             dry_run,
+            notice: None,
+            keymap,
+            mode: Mode::Insert,
+            show_help: false,
+            completion_cached: false,
+            prompt_version: None,
+            safety_check_cached: false,
+            show_debug: false,
+            debug_scroll: 0,
+            theme,
+            script_mode,
+            commentary_text: String::new(),
+            script_check_text: String::new(),
+            line_prompt: None,
+            form: None,
+            browser: None,
+            piped: None,
+            last_request: None,
         }
     }
 
+    /// Take events from the given handler, instead of reading the terminal.
+    pub fn with_events(mut self, events: EventHandler) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Start with a request, asking the model straight away.
+    pub fn with_prompt(mut self, prompt: String) -> Self {
+        self.input.set_text(prompt.clone());
+        self.events.send(AppEvent::RequestCompletion(prompt));
+        self
+    }
+
+    /// Feed data piped into uhh to the command when it runs.
+    pub fn with_piped(mut self, piped: Piped) -> Self {
+        self.piped = Some(piped);
+        self
+    }
+
     /// Run the application's main loop.
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> color_eyre::Result<()> {
+        self.events.resume();
         while self.running {
             terminal.draw(|frame| frame.render_widget(&self, frame.area()))?;
             match self.events.next().await? {
-                Event::Tick => self.tick(),
-                Event::Crossterm(event) => match event {
-                    crossterm::event::Event::Key(key_event) => self.handle_key_events(key_event)?,
-                    _ => {}
-                },
-                Event::App(app_event) => match app_event {
-                    AppEvent::Quit => self.quit(),
-                    AppEvent::RequestCompletion(input) => {
-                        self.handle_completion_request(input).await?;
-                    }
-                    AppEvent::CompletionResponse(response) => {
-                        self.is_loading_completion = false;
-                        self.response_text = response.clone();
-                        self.response_cursor = self.response_text.len();
-                        self.check_completion_request(response).await;
-                    }
-                    AppEvent::CompletionError(error) => {
-                        self.is_loading_completion = false;
-                        self.response_text = format!("Error: {}", error);
-                        self.response_cursor = self.response_text.len();
-                    }
-                    AppEvent::SafetyCheckResponse(response) => {
-                        self.is_loading_safety_check = false;
-                        self.safety_check_text = response.clone();
-
-                        if response.trim().starts_with('Y') {
-                            self.safety_status = SafetyStatus::Safe;
-                        } else if response.trim().starts_with('N') {
-                            self.safety_status = SafetyStatus::Unsafe;
-                        } else {
-                            self.safety_status = SafetyStatus::Unknown;
-                        }
-                    }
-                    AppEvent::SafetyCheckError(error) => {
-                        self.is_loading_safety_check = false;
-                        self.safety_check_text = format!("Safety check error: {}", error);
-                        self.safety_status = SafetyStatus::Unknown;
-                    }
-                    AppEvent::ExecuteCommand(command) => {
-                        self.execute_command(command)?;
-                    }
-                },
+                Event::App(AppEvent::OpenEditor) => self.open_editor(&mut terminal).await,
+                event => self.handle_event(event).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles an event from the terminal or the app, apart from opening the editor, which needs
+    /// the terminal and so is handled by [`App::run`].
+    pub async fn handle_event(&mut self, event: Event) -> color_eyre::Result<()> {
+        match event {
+            Event::Tick => self.tick(),
+            Event::Crossterm(event) => match event {
+                crossterm::event::Event::Key(key_event) => self.handle_key_events(key_event)?,
+                crossterm::event::Event::Paste(text) => self.handle_paste(&text),
+                _ => {}
+            },
+            Event::App(app_event) => self.handle_app_event(app_event).await?,
+        }
+        Ok(())
+    }
+
+    /// Handles an app event, such as a response arriving from the model.
+    pub async fn handle_app_event(&mut self, event: AppEvent) -> color_eyre::Result<()> {
+        match event {
+            AppEvent::Quit => self.quit(),
+            AppEvent::RequestCompletion(input) => {
+                self.handle_completion_request(input).await?;
+            }
+            AppEvent::CompletionResponse { content, cached } => {
+                self.is_loading_completion = false;
+                self.completion_cached = cached;
+                let templates = self.client.templates();
+                let template = if self.script_mode {
+                    &templates.script
+                } else {
+                    &templates.command
+                };
+                self.prompt_version = Some(template.version.clone());
+                let extraction = extract::extract(&content, self.script_mode);
+                if extraction.lossy {
+                    self.notice = Some(
+                        "Parts of the response may have been dropped, check the review pane"
+                            .to_string(),
+                    );
+                }
+                self.commentary_text = extraction.commentary;
+                let command = extraction.command;
+                self.replace_response(command.clone());
+                if self.script_mode {
+                    self.check_script(command.clone());
+                }
+                self.check_completion_request(command);
+            }
+            AppEvent::CompletionError(error) => {
+                self.is_loading_completion = false;
+                self.replace_response(format!("Error: {}", error));
             }
+            AppEvent::SafetyCheckResponse {
+                content: response,
+                cached,
+            } => {
+                self.is_loading_safety_check = false;
+                self.safety_check_cached = cached;
+                self.safety_status = SafetyStatus::from_review(&response);
+                self.safety_check_text = response;
+            }
+            AppEvent::SafetyCheckError(error) => {
+                self.is_loading_safety_check = false;
+                self.safety_check_text = format!("Safety check error: {}", error);
+                self.safety_status = SafetyStatus::Unknown;
+            }
+            AppEvent::ExecuteCommand(command) => {
+                self.execute_command(command)?;
+            }
+            // Opening the editor needs the terminal, so is handled by [`App::run`].
+            AppEvent::OpenEditor => {}
+            AppEvent::ScriptChecked(report) => self.script_check_text = report,
         }
         Ok(())
     }
 
     /// Handles the key events and updates the state of [`App`].
     pub fn handle_key_events(&mut self, key_event: KeyEvent) -> color_eyre::Result<()> {
-        // TODO: Messy, but okay for now
-        match key_event.code {
-            KeyCode::Esc => self.events.send(AppEvent::Quit),
-            KeyCode::Char('c' | 'C') if key_event.modifiers == KeyModifiers::CONTROL => {
-                self.events.send(AppEvent::Quit)
+        self.notice = None;
+        let key = KeyBinding::from(key_event);
+
+        if self.show_help {
+            // Any key dismisses the help overlay, without acting on it.
+            self.show_help = false;
+            return Ok(());
+        }
+
+        if self.show_debug {
+            // The debug pane scrolls with the arrow and page keys, and any other key closes it.
+            let page = PAGE_LINES as u16;
+            match key.code {
+                KeyCode::Up => self.debug_scroll = self.debug_scroll.saturating_sub(1),
+                KeyCode::Down => self.debug_scroll = self.debug_scroll.saturating_add(1),
+                KeyCode::PageUp => self.debug_scroll = self.debug_scroll.saturating_sub(page),
+                KeyCode::PageDown => self.debug_scroll = self.debug_scroll.saturating_add(page),
+                _ => self.show_debug = false,
+            }
+            return Ok(());
+        }
+
+        if self.line_prompt.is_some() {
+            self.handle_line_prompt_key(key);
+            return Ok(());
+        }
+
+        if let Some(browser) = self.browser.as_mut() {
+            match browser.handle_key(key) {
+                BrowserOutcome::Pending => {}
+                BrowserOutcome::Cancelled => self.browser = None,
+                BrowserOutcome::Recall(snippet) => {
+                    self.browser = None;
+                    self.recall_snippet(snippet);
+                }
+                BrowserOutcome::AskModel(prompt) => {
+                    self.browser = None;
+                    self.events.send(AppEvent::RequestCompletion(prompt));
+                }
+            }
+            return Ok(());
+        }
+
+        if let Some(form) = self.form.as_mut() {
+            match form.handle_key(key) {
+                FormOutcome::Pending => {}
+                FormOutcome::Cancelled => self.form = None,
+                FormOutcome::Submitted(values) => {
+                    let shell = self.client.shell();
+                    match placeholder::substitute(&form.command, &values, shell) {
+                        Ok(command) => {
+                            self.form = None;
+                            self.events.send(AppEvent::ExecuteCommand(command));
+                        }
+                        Err(e) => form.message = Some(e),
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let action = self.keymap.action(self.mode, key).filter(|_| {
+            // Printable keys are typed rather than bound while there is text to edit, so binding
+            // e.g. `?` doesn't stop it being typed.
+            self.mode == Mode::Normal
+                || !key.is_printable()
+                || self.buffer_mut(self.focused_pane).text().is_empty()
+        });
+
+        match (action, key.code) {
+            (Some(action), _) => self.perform(action),
+            (None, KeyCode::Char(c)) if self.mode == Mode::Insert && !has_command_modifier(key) => {
+                if c.is_whitespace() {
+                    // Start a new undo group at each word.
+                    self.undo_stack.break_group();
+                }
+                self.edit(EditKind::Insert, |buffer| buffer.insert_char(c));
             }
-            KeyCode::Up | KeyCode::Down => {
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Performs a keymap action.
+    pub fn perform(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.events.send(AppEvent::Quit),
+            Action::SwitchPane => {
                 self.focused_pane = if self.focused_pane == 0 { 1 } else { 0 };
             }
-            KeyCode::Enter if self.focused_pane == 0 => {
-                let input = self.input_text.clone();
-                self.events.send(AppEvent::RequestCompletion(input));
+            Action::Submit if self.focused_pane == 0 => {
+                let input = self.input.text().to_string();
+                // Offer any similar snippets, from the team's packs or the user's own library,
+                // before spending a request on the model.
+                let suggestions = Library::load()
+                    .ok()
+                    .filter(|_| !self.script_mode)
+                    .and_then(|library| SnippetBrowser::suggest(library, &input));
+                match suggestions {
+                    Some(browser) => self.browser = Some(browser),
+                    None => self.events.send(AppEvent::RequestCompletion(input)),
+                }
+            }
+            Action::Submit if self.script_mode => {
+                if !self.response.text().trim().is_empty() {
+                    self.open_line_prompt(LinePrompt::SaveScript, "script.sh");
+                }
+            }
+            Action::Submit => {
+                let command = self.response.text().to_string();
+                match PlaceholderForm::new(&command) {
+                    Some(form) => self.form = Some(form),
+                    None => self.events.send(AppEvent::ExecuteCommand(command)),
+                }
+            }
+            Action::OpenEditor => self.events.send(AppEvent::OpenEditor),
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::ToggleHelp => self.show_help = !self.show_help,
+            Action::ToggleDebug => {
+                self.show_debug = !self.show_debug;
+                self.debug_scroll = 0;
             }
-            KeyCode::Enter if self.focused_pane == 1 => {
-                let command = self.response_text.clone();
-                self.events.send(AppEvent::ExecuteCommand(command));
+            Action::MoveLeft => self.move_cursor(TextBuffer::move_left),
+            Action::MoveRight => self.move_cursor(TextBuffer::move_right),
+            Action::LineStart => self.move_cursor(TextBuffer::move_home),
+            Action::LineEnd => self.move_cursor(TextBuffer::move_end),
+            Action::PageUp => self.move_cursor(|buffer| buffer.move_lines(-PAGE_LINES)),
+            Action::PageDown => self.move_cursor(|buffer| buffer.move_lines(PAGE_LINES)),
+            Action::DeleteBackward => self.edit(EditKind::Delete, TextBuffer::backspace),
+            Action::DeleteForward => self.edit(EditKind::Delete, TextBuffer::delete),
+            Action::NormalMode => {
+                self.mode = Mode::Normal;
+                self.undo_stack.break_group();
             }
-            KeyCode::Char(c) if self.focused_pane == 0 => {
-                // Handle text input when top pane is focused
-                self.input_text.insert(self.input_cursor, c);
-                self.input_cursor += 1;
+            Action::InsertMode => self.mode = Mode::Insert,
+            Action::Append => {
+                self.move_cursor(TextBuffer::move_right);
+                self.mode = Mode::Insert;
             }
-            KeyCode::Backspace if self.focused_pane == 0 => {
-                if self.input_cursor > 0 {
-                    self.input_cursor -= 1;
-                    self.input_text.remove(self.input_cursor);
+            Action::SaveSnippet => {
+                if self.response.text().trim().is_empty() {
+                    self.notice = Some("There is no command to save yet".to_string());
+                } else {
+                    self.open_line_prompt(LinePrompt::SaveSnippet, "");
                 }
             }
-            KeyCode::Char(c) if self.focused_pane == 1 => {
-                // Handle text input when middle pane is focused
-                self.response_text.insert(self.response_cursor, c);
-                self.response_cursor += 1;
+            Action::OpenSnippets => match Library::load() {
+                Ok(library) => self.browser = Some(SnippetBrowser::new(library)),
+                Err(e) => self.notice = Some(format!("Could not load snippets: {}", e)),
+            },
+            Action::ToggleScriptMode => {
+                self.script_mode = !self.script_mode;
+                self.script_check_text.clear();
             }
-            KeyCode::Backspace if self.focused_pane == 1 => {
-                if self.response_cursor > 0 {
-                    self.response_cursor -= 1;
-                    self.response_text.remove(self.response_cursor);
+            Action::Unbound => {}
+        }
+    }
+
+    fn open_line_prompt(&mut self, prompt: LinePrompt, initial: &str) {
+        let mut text = TextBuffer::new();
+        text.set_text(initial);
+        self.line_prompt = Some((prompt, text));
+    }
+
+    /// Handles a key press while a line prompt is open.
+    fn handle_line_prompt_key(&mut self, key: KeyBinding) {
+        let Some((prompt, text)) = self.line_prompt.as_mut() else {
+            return;
+        };
+        if *prompt == LinePrompt::OverwriteScript && key.code != KeyCode::Enter {
+            *prompt = LinePrompt::SaveScript;
+        }
+        match key.code {
+            KeyCode::Esc => self.line_prompt = None,
+            KeyCode::Enter => {
+                let prompt = *prompt;
+                let text = text.text().trim().to_string();
+                if !text.is_empty() {
+                    self.line_prompt = None;
+                    match prompt {
+                        LinePrompt::SaveScript => self.save_script(&text, false),
+                        LinePrompt::OverwriteScript => self.save_script(&text, true),
+                        LinePrompt::SaveSnippet => self.save_snippet(&text),
+                    }
                 }
             }
-            // Other handlers you could add here.
+            KeyCode::Char(c) if !has_command_modifier(key) => text.insert_char(c),
+            KeyCode::Backspace => text.backspace(),
+            KeyCode::Delete => text.delete(),
+            KeyCode::Left => text.move_left(),
+            KeyCode::Right => text.move_right(),
+            KeyCode::Home => text.move_home(),
+            KeyCode::End => text.move_end(),
             _ => {}
         }
-        Ok(())
+    }
+
+    /// Save the generated script and exit, asking before replacing an existing file, or report
+    /// why it couldn't be saved.
+    fn save_script(&mut self, path: &str, overwrite: bool) {
+        match script::save(Path::new(path), self.response.text(), overwrite) {
+            Ok(()) => {
+                terminal::restore();
+                println!("Uhh: Saved script to {}", path);
+                std::process::exit(0);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                self.open_line_prompt(LinePrompt::OverwriteScript, path);
+            }
+            Err(e) => self.notice = Some(format!("Could not save {}: {}", path, e)),
+        }
+    }
+
+    /// Save the current prompt and command to the snippet library.
+    ///
+    /// `name_and_tags` is the name followed by any `#tags`, as typed in the prompt.
+    fn save_snippet(&mut self, name_and_tags: &str) {
+        let (name, tags) = snippets::parse_name_and_tags(name_and_tags);
+        if name.is_empty() {
+            self.notice = Some("Snippets need a name as well as tags".to_string());
+            return;
+        }
+        let explanation = if self.commentary_text.is_empty() {
+            self.safety_check_text.clone()
+        } else {
+            self.commentary_text.clone()
+        };
+        let snippet = Snippet {
+            name: name.clone(),
+            tags,
+            prompt: self.input.text().to_string(),
+            command: self.response.text().to_string(),
+            explanation,
+            pack: None,
+        };
+
+        let result = Library::load().and_then(|mut library| {
+            library.add(snippet);
+            library.save()
+        });
+        self.notice = Some(match result {
+            Ok(()) => format!("Saved snippet {}", name),
+            Err(e) => format!("Could not save snippet: {}", e),
+        });
+    }
+
+    /// Load a snippet into the panes, without asking the model for anything.
+    fn recall_snippet(&mut self, snippet: Snippet) {
+        self.replace_text(0, snippet.prompt);
+        self.replace_text(1, snippet.command.clone());
+        self.commentary_text = snippet.explanation;
+        self.script_check_text.clear();
+        self.forget_review();
+        self.completion_cached = false;
+        self.prompt_version = None;
+        self.focused_pane = 1;
+        self.notice = Some(format!("Recalled snippet {}", snippet.name));
+        // A snippet, especially one from someone else's pack, gets the same check as a command
+        // from the model.
+        self.check_completion_request(snippet.command);
+    }
+
+    /// Handles text pasted into the terminal, inserting it into the focused pane.
+    pub fn handle_paste(&mut self, text: &str) {
+        self.edit(EditKind::Paste, |buffer| buffer.insert_str(text));
+    }
+
+    /// The text buffer of the given pane.
+    fn buffer_mut(&mut self, pane: usize) -> &mut TextBuffer {
+        if pane == 0 {
+            &mut self.input
+        } else {
+            &mut self.response
+        }
+    }
+
+    /// Apply an edit to the focused pane, recording it for undo if it changed the text.
+    fn edit(&mut self, kind: EditKind, edit: impl FnOnce(&mut TextBuffer)) {
+        let pane = self.focused_pane;
+        let before = self.buffer_mut(pane).clone();
+        edit(self.buffer_mut(pane));
+        if self.buffer_mut(pane).text() != before.text() {
+            self.undo_stack.record(pane, &before, kind);
+        }
+    }
+
+    /// Move the cursor in the focused pane.
+    fn move_cursor(&mut self, movement: impl FnOnce(&mut TextBuffer)) {
+        movement(self.buffer_mut(self.focused_pane));
+        self.undo_stack.break_group();
+    }
+
+    /// Replace the text of a pane, keeping the previous text available to undo.
+    fn replace_text(&mut self, pane: usize, text: String) {
+        if self.buffer_mut(pane).text() != text {
+            let before = self.buffer_mut(pane).clone();
+            self.undo_stack.record(pane, &before, EditKind::Replace);
+        }
+        self.buffer_mut(pane).set_text(text);
+    }
+
+    /// Replace the generated command, keeping the previous one available to undo.
+    fn replace_response(&mut self, text: String) {
+        self.replace_text(1, text);
+    }
+
+    /// Suspend the TUI and edit the focused pane in `$VISUAL` or `$EDITOR`.
+    async fn open_editor(&mut self, terminal: &mut DefaultTerminal) {
+        let pane = self.focused_pane;
+        let text = self.buffer_mut(pane).text().to_string();
+        // The generated command is a shell command, the prompt is prose.
+        let extension = if pane == 1 { "sh" } else { "txt" };
+
+        self.events.pause().await;
+        terminal::restore();
+        let result = editor::edit_text(&text, extension);
+        *terminal = terminal::init();
+        self.events.resume();
+
+        match result {
+            Ok(edited) => self.replace_text(pane, edited),
+            Err(e) => self.notice = Some(format!("Editor failed: {}", e)),
+        }
+    }
+
+    /// Undo the last edit, focusing the pane it applied to.
+    pub fn undo(&mut self) {
+        let (input, response) = (&mut self.input, &mut self.response);
+        if let Some(pane) = self
+            .undo_stack
+            .undo(|snapshot| swap_snapshot(input, response, snapshot))
+        {
+            self.restored(pane);
+        }
+    }
+
+    /// Redo the last undone edit, focusing the pane it applied to.
+    pub fn redo(&mut self) {
+        let (input, response) = (&mut self.input, &mut self.response);
+        if let Some(pane) = self
+            .undo_stack
+            .redo(|snapshot| swap_snapshot(input, response, snapshot))
+        {
+            self.restored(pane);
+        }
+    }
+
+    /// Focus a pane restored by undo or redo. A restored command hasn't been through the
+    /// safety check shown for the command it replaced, so that review is dropped.
+    fn restored(&mut self, pane: usize) {
+        self.focused_pane = pane;
+        if pane == 1 {
+            self.forget_review();
+        }
+    }
+
+    /// Clear the safety review, for when the command it was of is gone.
+    fn forget_review(&mut self) {
+        self.safety_check_text.clear();
+        self.safety_status = SafetyStatus::Unknown;
+        self.safety_check_cached = false;
     }
 
     /// Handles the tick event of the terminal.
@@ -169,17 +655,34 @@ impl App {
         }
 
         self.is_loading_completion = true;
+        self.completion_cached = false;
+        self.safety_check_cached = false;
         self.safety_status = SafetyStatus::Unknown;
+        self.commentary_text.clear();
+        self.script_check_text.clear();
 
-        let client = self.client.clone();
+        let script_mode = self.script_mode;
+        let request = Some((input.clone(), script_mode));
+        let client = if self.last_request == request {
+            self.client.clone().regenerating()
+        } else {
+            self.client.clone()
+        };
+        self.last_request = request;
         let sender = self.events.sender.clone();
         tokio::spawn(async move {
-            match client.imagine_command(input).await {
+            let response = if script_mode {
+                client.imagine_script(input).await
+            } else {
+                client.imagine_command(input).await
+            };
+            match response {
                 Ok(response) => {
                     if let Some(choice) = response.choices.first() {
-                        let _ = sender.send(Event::App(AppEvent::CompletionResponse(
-                            choice.message.content.clone(),
-                        )));
+                        let _ = sender.send(Event::App(AppEvent::CompletionResponse {
+                            content: choice.message.content.clone(),
+                            cached: response.cached,
+                        }));
                     } else {
                         let _ = sender.send(Event::App(AppEvent::CompletionError(
                             "No response received".to_string(),
@@ -195,19 +698,37 @@ impl App {
         Ok(())
     }
 
-    async fn check_completion_request(&mut self, input: String) {
+    /// Run static checks on a generated script in the background.
+    fn check_script(&self, script: String) {
+        let sender = self.events.sender.clone();
+        tokio::spawn(async move {
+            let report = script::check(&script).await;
+            let _ = sender.send(Event::App(AppEvent::ScriptChecked(report)));
+        });
+    }
+
+    /// Run the safety check on a command in the background.
+    fn check_completion_request(&mut self, input: String) {
         self.is_loading_safety_check = true;
+        self.safety_check_cached = false;
 
         let infer = self.client.clone();
         let sender = self.events.sender.clone();
+        let script_mode = self.script_mode;
 
         tokio::spawn(async move {
-            match infer.inspect_command(input).await {
+            let response = if script_mode {
+                infer.inspect_script(input).await
+            } else {
+                infer.inspect_command(input).await
+            };
+            match response {
                 Ok(response) => {
                     if let Some(choice) = response.choices.first() {
-                        let _ = sender.send(Event::App(AppEvent::SafetyCheckResponse(
-                            choice.message.content.clone(),
-                        )));
+                        let _ = sender.send(Event::App(AppEvent::SafetyCheckResponse {
+                            content: choice.message.content.clone(),
+                            cached: response.cached,
+                        }));
                     } else {
                         let _ = sender.send(Event::App(AppEvent::SafetyCheckError(
                             "No safety check response received".to_string(),
@@ -221,13 +742,18 @@ impl App {
         });
     }
 
-    /// Execute the command and replace the current process.
+    /// Run the command in the user's shell, record it in the history and exit with its status.
+    ///
+    /// uhh waits for the command rather than replacing itself with it, as it once did, so that
+    /// the outcome can be recorded and piped input can be fed to it. While it runs, uhh stands
+    /// aside as a shell would: Ctrl+C only interrupts the command, a SIGTERM or SIGHUP sent to
+    /// uhh is passed on to it, and a command killed by a signal is reported as such.
     fn execute_command(&mut self, command: String) -> color_eyre::Result<()> {
         if command.trim().is_empty() {
             return Ok(());
         }
 
-        ratatui::restore();
+        terminal::restore();
 
         if let Ok(mut p) = Clipboard::new() {
             match p.set_text(&command) {
@@ -236,7 +762,6 @@ impl App {
             }
         }
 
-
         println!("----");
 
         if self.dry_run {
@@ -248,30 +773,138 @@ impl App {
         // but it has to be called inside a fish shell, I can't spawn a subshell and run
         // that inside.
 
-        #[cfg(windows)]
+        let shell = self.client.shell();
+        let mut child = spawn_command(shell, &command, self.piped.take())
+            .map_err(|e| color_eyre::eyre::eyre!("Failed to execute command: {}", e))?;
+        #[cfg(unix)]
+        signals::pass_to(child.id());
+        let status = child
+            .wait()
+            .map_err(|e| color_eyre::eyre::eyre!("Failed to execute command: {}", e))?;
+
+        let entry = HistoryEntry::new(
+            shell,
+            self.input.text().to_string(),
+            command,
+            status.code(),
+            self.prompt_version.clone(),
+        );
+        if let Err(e) = History::append(&entry) {
+            eprintln!("Uhh: Could not record the command in the history: {}", e);
+        }
+        #[cfg(unix)]
         {
-            use std::process::Stdio;
+            use std::os::unix::process::ExitStatusExt;
+
+            // Exit as a shell does for a command killed by a signal.
+            if let Some(signal) = status.signal() {
+                std::process::exit(128 + signal);
+            }
+        }
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
 
-            let mut cmd = Command::new("powershell");
-            cmd.arg("-c").arg(command);
+/// Start a command in a shell, feeding it any piped input from another thread.
+fn spawn_command(
+    shell: Shell,
+    command: &str,
+    piped: Option<Piped>,
+) -> io::Result<std::process::Child> {
+    let mut child = shell.command(command);
+    if piped.is_some() {
+        child.stdin(Stdio::piped());
+    }
+    let mut child = child.spawn()?;
+    if let (Some(piped), Some(stdin)) = (piped, child.stdin.take()) {
+        std::thread::spawn(move || {
+            if let Err(e) = piped.feed(stdin) {
+                eprintln!(
+                    "Uhh: Could not pass the piped input on to the command: {}",
+                    e
+                );
+            }
+        });
+    }
+    Ok(child)
+}
+
+/// Swap a snapshot into its pane, returning the state it replaced.
+fn swap_snapshot(
+    input: &mut TextBuffer,
+    response: &mut TextBuffer,
+    snapshot: Snapshot,
+) -> Snapshot {
+    let target = if snapshot.pane == 0 { input } else { response };
+    Snapshot {
+        pane: snapshot.pane,
+        buffer: std::mem::replace(target, snapshot.buffer),
+    }
+}
+
+/// Whether a key is held with control or alt, and so is a command rather than text.
+fn has_command_modifier(key: KeyBinding) -> bool {
+    key.modifiers
+        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+}
+
+/// Signal handling while a command runs in the foreground.
+#[cfg(unix)]
+mod signals {
+    use std::sync::atomic::{AtomicI32, Ordering};
 
-            cmd.stdin(Stdio::inherit())
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit());
+    /// The command signals are passed on to.
+    static COMMAND: AtomicI32 = AtomicI32::new(0);
 
-            if let Err(e) = cmd.spawn() {
-                return Err(color_eyre::eyre::eyre!("Failed to execute command: {}", e));
+    extern "C" fn ignore(_signal: libc::c_int) {}
+
+    extern "C" fn pass_on(signal: libc::c_int) {
+        let pid = COMMAND.load(Ordering::SeqCst);
+        if pid > 0 {
+            // SAFETY: kill is async-signal-safe.
+            unsafe {
+                libc::kill(pid, signal);
             }
         }
+    }
 
-        #[cfg(unix)]
-        {
-            use std::os::unix::process::CommandExt;
-            let mut cmd = Command::new("sh");
-            cmd.arg("-c").arg(command);
-
-            let e = cmd.exec();
-            return Err(color_eyre::eyre::eyre!("Failed to execute command: {}", e));
+    /// Leave the command to handle Ctrl+C and Ctrl+\, which the terminal sends it as well as
+    /// uhh, and pass SIGTERM and SIGHUP on to it.
+    ///
+    /// Handlers are reset to the default when a program is executed, unlike ignored signals,
+    /// so any children spawned later still get the usual behaviour.
+    pub fn pass_to(pid: u32) {
+        COMMAND.store(pid as i32, Ordering::SeqCst);
+        let ignore = ignore as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        let pass_on = pass_on as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // SAFETY: the handlers only make async-signal-safe calls.
+        unsafe {
+            libc::signal(libc::SIGINT, ignore);
+            libc::signal(libc::SIGQUIT, ignore);
+            libc::signal(libc::SIGTERM, pass_on);
+            libc::signal(libc::SIGHUP, pass_on);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_are_fed_the_piped_input() {
+        let path = std::env::temp_dir().join(format!("uhh-app-fed-{}", std::process::id()));
+        let data: String = (1..=5000).map(|i| format!("line {}\n", i)).collect();
+        let piped = Piped::new(std::io::Cursor::new(data.clone().into_bytes()));
+
+        let command = format!("cat > {}", Shell::Sh.quote(&path.display().to_string()));
+        let status = spawn_command(Shell::Sh, &command, Some(piped))
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), data);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::infer::CompletionRequest;
+use crate::usage::Usage;
+
+/// How many exchanges are kept in memory for the debug pane.
+const RECENT: usize = 10;
+
+/// Audit log settings, from the `[audit]` section of the config.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Append every request and response to the audit log.
+    pub log: bool,
+    /// Where to write the log, instead of `audit.jsonl` in the data directory.
+    pub path: Option<PathBuf>,
+}
+
+impl AuditConfig {
+    /// The path to log to, or `None` if logging is off.
+    pub fn log_path(&self) -> Result<Option<PathBuf>> {
+        if !self.log {
+            return Ok(None);
+        }
+        match &self.path {
+            Some(path) => Ok(Some(path.clone())),
+            None => dirs::data_dir()
+                .map(|dir| Some(dir.join("uhh").join("audit.jsonl")))
+                .ok_or_else(|| eyre!("Could not find a data directory for the audit log")),
+        }
+    }
+}
+
+/// A request sent to the provider, and what came back.
+///
+/// Both are recorded exactly as they crossed the wire, so secrets appear as placeholders.
+#[derive(Debug, Clone, Serialize)]
+pub struct Exchange {
+    /// When the request was sent, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub model: String,
+    pub request: CompletionRequest,
+    /// The HTTP status, or `None` if no response arrived or it came from the cache.
+    pub status: Option<u16>,
+    /// The raw response body, or the error if no response arrived.
+    pub response: String,
+    pub duration_ms: u64,
+    /// The token usage reported by the provider, if any.
+    pub usage: Option<Usage>,
+    /// How many distinct secrets were redacted from the request.
+    pub redacted: usize,
+    /// Whether the response came from the cache, in which case nothing was sent.
+    pub cached: bool,
+}
+
+impl Exchange {
+    pub fn timestamp_now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+/// Records exchanges with the provider, for the debug pane and the opt-in audit log.
+///
+/// Clones share the same record, so exchanges made from background tasks show up in the app.
+#[derive(Debug, Clone, Default)]
+pub struct Audit {
+    log_path: Option<PathBuf>,
+    recent: Arc<Mutex<VecDeque<Exchange>>>,
+    /// Why the last exchange couldn't be appended to the audit log, if it couldn't.
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+impl Audit {
+    pub fn new(log_path: Option<PathBuf>) -> Self {
+        Self {
+            log_path,
+            recent: Arc::default(),
+            failure: Arc::default(),
+        }
+    }
+
+    /// Keep an exchange for the debug pane, and append it to the audit log if there is one.
+    ///
+    /// A log that can't be written doesn't stop the request it records, but is reported by
+    /// [`Audit::failure`] until an exchange is logged again.
+    pub fn record(&self, exchange: Exchange) {
+        let Some(path) = &self.log_path else {
+            self.keep(exchange);
+            return;
+        };
+        let result = self.append(path, &exchange);
+        *self.failure.lock().unwrap_or_else(|e| e.into_inner()) =
+            result.err().map(|e| format!("{:#}", e));
+        self.keep(exchange);
+    }
+
+    /// Why the audit log couldn't be written to, for the status line.
+    pub fn failure(&self) -> Option<String> {
+        self.failure
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn keep(&self, exchange: Exchange) {
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        if recent.len() == RECENT {
+            recent.pop_front();
+        }
+        recent.push_back(exchange);
+    }
+
+    fn append(&self, path: &Path, exchange: &Exchange) -> Result<()> {
+        let line = serde_json::to_string(exchange)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .wrap_err_with(|| format!("Failed to open the audit log {}", path.display()))?;
+        writeln!(file, "{}", line)
+            .wrap_err_with(|| format!("Failed to write the audit log {}", path.display()))
+    }
+
+    /// The most recent exchanges, newest first.
+    pub fn recent(&self) -> Vec<Exchange> {
+        let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        recent.iter().rev().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::{CompletionRequest, InferenceEngine, Message};
+    use crate::inputs::Inputs;
+    use crate::shell::Shell;
+    use crate::transport::{RawResponse, Transport};
+    use futures::future::BoxFuture;
+
+    /// Answers every request with the last message it was sent.
+    #[derive(Debug)]
+    struct Echo;
+
+    impl Transport for Echo {
+        fn send<'a>(
+            &'a self,
+            request: &'a CompletionRequest,
+        ) -> BoxFuture<'a, Result<RawResponse>> {
+            let content = request.messages.last().unwrap().content.clone();
+            let body = serde_json::json!({ "choices": [{ "message": {
+                "role": "assistant",
+                "content": content,
+            }}]});
+            Box::pin(async move {
+                Ok(RawResponse {
+                    status: 200,
+                    body: body.to_string(),
+                })
+            })
+        }
+    }
+
+    fn engine(audit: Audit) -> InferenceEngine {
+        InferenceEngine::new(
+            String::new(),
+            String::new(),
+            "test/model".into(),
+            Inputs::default(),
+            Vec::new(),
+            Shell::Bash,
+        )
+        .unwrap()
+        .with_transport(Arc::new(Echo))
+        .with_audit(audit)
+    }
+
+    fn request(content: &str) -> CompletionRequest {
+        CompletionRequest {
+            model: "test/model".into(),
+            messages: vec![Message {
+                role: "user".into(),
+                content: content.into(),
+            }],
+            max_tokens: None,
+            temperature: None,
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn appends_redacted_exchanges_as_json_lines() {
+        let path =
+            std::env::temp_dir().join(format!("uhh-audit-{}/audit.jsonl", std::process::id()));
+        let engine = engine(Audit::new(Some(path.clone())));
+
+        let secret = "sk-abcdefghijklmnopqrstuvwxyz";
+        let response = engine
+            .completion(request(&format!("export OPENAI_API_KEY={}", secret)))
+            .await
+            .unwrap();
+        assert!(response.choices[0].message.content.contains(secret));
+        engine.completion(request("ls -l")).await.unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(!log.contains(secret), "{}", log);
+        let lines: Vec<serde_json::Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["model"], "test/model");
+        assert_eq!(lines[0]["redacted"], 1);
+        assert_eq!(lines[0]["status"], 200);
+        let sent = lines[0]["request"]["messages"].as_array().unwrap();
+        assert!(
+            sent.last().unwrap()["content"]
+                .as_str()
+                .unwrap()
+                .ends_with("=__UHH_SECRET_1__")
+        );
+        assert!(
+            lines[0]["response"]
+                .as_str()
+                .unwrap()
+                .contains("__UHH_SECRET_1__")
+        );
+        assert_eq!(lines[1]["redacted"], 0);
+        assert_eq!(engine.recent_exchanges().len(), 2);
+        assert_eq!(engine.audit_failure(), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "current_thread")]
+    async fn unwritable_logs_dont_fail_requests() {
+        let path = PathBuf::from("/dev/null/audit.jsonl");
+        let engine = engine(Audit::new(Some(path)));
+        let response = engine.completion(request("ls -l")).await.unwrap();
+        assert_eq!(response.choices[0].message.content, "ls -l");
+        assert!(engine.audit_failure().is_some());
+        assert_eq!(engine.recent_exchanges().len(), 1);
+    }
+}
@@ -0,0 +1,39 @@
+use clap::Parser;
+use std::path::PathBuf;
+use tokio::net::TcpListener;
+
+use uhh::mock::{MockScript, MockServer};
+
+/// Serve scripted chat completions, for trying out uhh's error handling and loading states
+/// without a real provider.
+///
+/// Point uhh at it with `uhh --base-url http://127.0.0.1:<port>/v1`.
+#[derive(Parser)]
+#[command(name = "uhh-mock-server")]
+#[command(version)]
+struct Args {
+    /// The port to listen on. 0 picks a free one.
+    #[arg(short, long, default_value_t = 8787)]
+    port: u16,
+    /// A TOML script of responses to give. By default every safety check is approved and every
+    /// other request gets the same command.
+    #[arg(short, long)]
+    script: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let args = Args::parse();
+    let script = match &args.script {
+        Some(path) => MockScript::load(path)?,
+        None => MockScript::default(),
+    };
+
+    let listener = TcpListener::bind(("127.0.0.1", args.port)).await?;
+    println!(
+        "Uhh: Mock server listening on http://{}/v1",
+        listener.local_addr()?
+    );
+    MockServer::new(script).serve(listener).await
+}
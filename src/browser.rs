@@ -0,0 +1,226 @@
+use ratatui::crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::keymap::KeyBinding;
+use crate::snippets::{Library, Snippet};
+use crate::text::TextBuffer;
+
+/// How many snippets to suggest for a prompt.
+const SUGGESTIONS: usize = 5;
+
+/// What happened after a key press in the snippet browser.
+#[derive(Debug, PartialEq)]
+pub enum BrowserOutcome {
+    /// The browser is still open.
+    Pending,
+    /// The user backed out.
+    Cancelled,
+    /// The user picked a snippet to recall.
+    Recall(Snippet),
+    /// The user passed over the suggested snippets, and wants the model to answer the prompt.
+    AskModel(String),
+}
+
+/// An overlay for searching the snippet library.
+#[derive(Debug)]
+pub struct SnippetBrowser {
+    pub library: Library,
+    pub query: TextBuffer,
+    /// The index of the selected snippet among the matches.
+    pub selected: usize,
+    /// An error from the last action, if any.
+    pub message: Option<String>,
+    /// The prompt snippets were suggested for, until the user starts searching.
+    ///
+    /// While suggesting, the matches are the snippets most similar to the prompt, followed by an
+    /// entry to ask the model instead.
+    pub suggesting_for: Option<String>,
+}
+
+impl SnippetBrowser {
+    pub fn new(library: Library) -> Self {
+        Self {
+            query: TextBuffer::new(),
+            selected: 0,
+            message: library_message(&library),
+            library,
+            suggesting_for: None,
+        }
+    }
+
+    /// A browser offering the snippets most similar to `prompt`, or `None` if nothing is similar.
+    pub fn suggest(library: Library, prompt: &str) -> Option<Self> {
+        if library.suggest(prompt, SUGGESTIONS).is_empty() {
+            return None;
+        }
+        let mut browser = Self::new(library);
+        browser.suggesting_for = Some(prompt.to_string());
+        Some(browser)
+    }
+
+    /// The snippets matching the current query, or the suggestions for the prompt.
+    pub fn matches(&self) -> Vec<&Snippet> {
+        match &self.suggesting_for {
+            Some(prompt) => self.library.suggest(prompt, SUGGESTIONS),
+            None => self.library.search(self.query.text()),
+        }
+    }
+
+    /// The number of selectable entries, including the entry to ask the model when suggesting.
+    fn entries(&self) -> usize {
+        self.matches().len() + usize::from(self.suggesting_for.is_some())
+    }
+
+    /// Handles a key press.
+    ///
+    /// Typing searches, Up and Down select, Enter recalls the selected snippet, Ctrl+D deletes it
+    /// and Esc closes the browser.
+    pub fn handle_key(&mut self, key: KeyBinding) -> BrowserOutcome {
+        self.message = None;
+        let count = self.entries();
+        match key.code {
+            KeyCode::Esc => return BrowserOutcome::Cancelled,
+            KeyCode::Enter => {
+                if let Some(snippet) = self.matches().get(self.selected) {
+                    return BrowserOutcome::Recall((*snippet).clone());
+                }
+                if let Some(prompt) = self.suggesting_for.take() {
+                    return BrowserOutcome::AskModel(prompt);
+                }
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(count.saturating_sub(1)),
+            KeyCode::Char('d') if key.modifiers == KeyModifiers::CONTROL => self.delete_selected(),
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.suggesting_for = None;
+                self.query.insert_char(c);
+                self.selected = 0;
+            }
+            KeyCode::Backspace => {
+                self.suggesting_for = None;
+                self.query.backspace();
+                self.selected = 0;
+            }
+            KeyCode::Left => self.query.move_left(),
+            KeyCode::Right => self.query.move_right(),
+            _ => {}
+        }
+        BrowserOutcome::Pending
+    }
+
+    fn delete_selected(&mut self) {
+        let Some(snippet) = self.matches().get(self.selected).map(|s| (*s).clone()) else {
+            return;
+        };
+        if let Some(pack) = &snippet.pack {
+            self.message = Some(format!(
+                "{} belongs to the {} pack, and can't be deleted here",
+                snippet.name, pack
+            ));
+            return;
+        }
+        let name = snippet.name;
+        self.library.remove(&name);
+        if let Err(e) = self.library.save() {
+            self.message = Some(format!("Could not delete {}: {}", name, e));
+        }
+        self.selected = self.selected.min(self.entries().saturating_sub(1));
+    }
+}
+
+/// Report any packs that couldn't be loaded.
+fn library_message(library: &Library) -> Option<String> {
+    match library.warnings.as_slice() {
+        [] => None,
+        [warning] => Some(format!("Skipped a snippet pack: {}", warning)),
+        warnings => Some(format!(
+            "Skipped {} snippet packs: {}",
+            warnings.len(),
+            warnings[0]
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snippets::SnippetPack;
+
+    fn library() -> Library {
+        let snippet = |name: &str, prompt: &str, command: &str| Snippet {
+            name: name.into(),
+            tags: Vec::new(),
+            prompt: prompt.into(),
+            command: command.into(),
+            explanation: String::new(),
+            pack: None,
+        };
+        let mut pack_snippet = snippet("disk usage", "show disk usage", "df -h");
+        pack_snippet.pack = Some("team-ops".into());
+        Library {
+            snippets: vec![snippet(
+                "biggest files",
+                "list the biggest files",
+                "du -sh * | sort -h",
+            )],
+            packs: vec![SnippetPack {
+                format: 1,
+                name: "team-ops".into(),
+                version: "1.0.0".into(),
+                description: String::new(),
+                snippets: vec![pack_snippet],
+                path: None,
+            }],
+            warnings: Vec::new(),
+        }
+    }
+
+    fn press(browser: &mut SnippetBrowser, key: &str) -> BrowserOutcome {
+        browser.handle_key(key.parse().unwrap())
+    }
+
+    #[test]
+    fn searching_and_recalling() {
+        let mut browser = SnippetBrowser::new(library());
+        assert_eq!(browser.matches().len(), 2);
+        for c in "sort".chars() {
+            press(&mut browser, &c.to_string());
+        }
+        let names: Vec<&str> = browser.matches().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["biggest files"]);
+        match press(&mut browser, "enter") {
+            BrowserOutcome::Recall(snippet) => assert_eq!(snippet.command, "du -sh * | sort -h"),
+            outcome => panic!("expected a recall, got {:?}", outcome),
+        }
+        assert_eq!(press(&mut browser, "esc"), BrowserOutcome::Cancelled);
+    }
+
+    #[test]
+    fn suggestions_end_with_asking_the_model() {
+        assert!(SnippetBrowser::suggest(library(), "restart nginx").is_none());
+
+        let mut browser = SnippetBrowser::suggest(library(), "show the disk usage").unwrap();
+        let names: Vec<&str> = browser.matches().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["disk usage"]);
+        press(&mut browser, "down");
+        press(&mut browser, "down");
+        assert_eq!(
+            press(&mut browser, "enter"),
+            BrowserOutcome::AskModel("show the disk usage".into())
+        );
+    }
+
+    #[test]
+    fn pack_snippets_cannot_be_deleted() {
+        let mut browser = SnippetBrowser::new(library());
+        assert_eq!(press(&mut browser, "ctrl+d"), BrowserOutcome::Pending);
+        assert_eq!(browser.matches().len(), 2);
+        assert_eq!(
+            browser.message.as_deref(),
+            Some("disk usage belongs to the team-ops pack, and can't be deleted here")
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use serde::{Deserialize, Serialize};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::infer::CompletionRequest;
+
+/// Cache settings, from the `[cache]` section of the config.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// How long responses are reused for, in hours.
+    pub ttl_hours: u64,
+    /// The most responses to keep, dropping the oldest beyond that.
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_hours: 24 * 7,
+            max_entries: 1000,
+        }
+    }
+}
+
+/// A cached response body, as it came from the provider.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// When the response was cached, in seconds since the Unix epoch.
+    created: u64,
+    body: String,
+}
+
+/// An on-disk cache of responses, keyed on everything that affects them: the model, messages,
+/// temperature and token limit.
+///
+/// Requests are keyed after redaction, so secrets are never written to the cache, and a cached
+/// response is restored with whatever secrets the new request had.
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+    /// The directory holding the cache, or `None` if caching is off.
+    dir: Option<PathBuf>,
+    ttl_secs: u64,
+    max_entries: usize,
+}
+
+impl Cache {
+    /// Where the cache is stored, e.g. `~/.cache/uhh/responses` on Linux.
+    pub fn default_dir() -> Result<PathBuf> {
+        dirs::cache_dir()
+            .map(|dir| dir.join("uhh").join("responses"))
+            .ok_or_else(|| eyre!("Could not find a cache directory"))
+    }
+
+    /// A cache in `dir`, or a cache that never hits if `dir` is `None` or caching is disabled.
+    pub fn new(dir: Option<PathBuf>, config: &CacheConfig) -> Self {
+        Self {
+            dir: dir.filter(|_| config.enabled),
+            ttl_secs: config.ttl_hours * 60 * 60,
+            max_entries: config.max_entries,
+        }
+    }
+
+    /// The cache key for a request.
+    pub fn key(request: &CompletionRequest) -> String {
+        // FNV is used over the standard library's hasher, whose output may change between
+        // versions of Rust.
+        let mut hasher = fnv::FnvHasher::default();
+        hasher.write(&serde_json::to_vec(request).unwrap_or_default());
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// The cached response body for a key, if there is one that hasn't expired.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let path = self.dir.as_ref()?.join(format!("{}.json", key));
+        let entry: Entry = serde_json::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+        if now().saturating_sub(entry.created) > self.ttl_secs {
+            let _ = std::fs::remove_file(&path);
+            return None;
+        }
+        Some(entry.body)
+    }
+
+    /// Cache a response body, dropping the oldest responses if there are too many.
+    pub fn put(&self, key: &str, body: &str) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+        let entry = Entry {
+            created: now(),
+            body: body.to_string(),
+        };
+        let path = dir.join(format!("{}.json", key));
+        std::fs::write(&path, serde_json::to_string(&entry)?)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+        self.evict(dir)
+    }
+
+    /// Remove the oldest responses beyond the size limit.
+    fn evict(&self, dir: &Path) -> Result<()> {
+        let mut entries: Vec<(SystemTime, PathBuf)> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect();
+        if entries.len() <= self.max_entries {
+            return Ok(());
+        }
+        entries.sort();
+        let excess = entries.len() - self.max_entries;
+        for (_, path) in entries.into_iter().take(excess) {
+            std::fs::remove_file(&path)
+                .wrap_err_with(|| format!("Failed to remove {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::Message;
+
+    fn request(content: &str) -> CompletionRequest {
+        CompletionRequest {
+            model: "model".into(),
+            messages: vec![Message {
+                role: "user".into(),
+                content: content.into(),
+            }],
+            max_tokens: Some(100),
+            temperature: Some(0.7),
+        }
+    }
+
+    #[test]
+    fn reuses_responses_until_evicted() {
+        let dir = std::env::temp_dir().join(format!("uhh-cache-test-{}", std::process::id()));
+        let config = CacheConfig {
+            max_entries: 1,
+            ..CacheConfig::default()
+        };
+        let cache = Cache::new(Some(dir.clone()), &config);
+        let (first, second) = (Cache::key(&request("a")), Cache::key(&request("b")));
+        assert_ne!(first, second);
+        assert_eq!(first, Cache::key(&request("a")));
+
+        cache.put(&first, "first").unwrap();
+        assert_eq!(cache.get(&first).as_deref(), Some("first"));
+        // Modification times can be too coarse to order the entries otherwise.
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put(&second, "second").unwrap();
+        assert_eq!(cache.get(&first), None);
+        assert_eq!(cache.get(&second).as_deref(), Some("second"));
+
+        let disabled = Cache::new(
+            Some(dir.clone()),
+            &CacheConfig {
+                enabled: false,
+                ..CacheConfig::default()
+            },
+        );
+        assert_eq!(disabled.get(&second), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
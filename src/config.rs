@@ -0,0 +1,45 @@
+use color_eyre::{Result, eyre::WrapErr};
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::audit::AuditConfig;
+use crate::cache::CacheConfig;
+use crate::keymap::KeysConfig;
+use crate::redact::RedactConfig;
+use crate::theme::ThemeName;
+use crate::usage::UsageConfig;
+
+/// User configuration, read from `config.toml` in the config directory.
+///
+/// Every section is optional, and anything left out falls back to the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub theme: ThemeName,
+    pub keys: KeysConfig,
+    pub redact: RedactConfig,
+    pub audit: AuditConfig,
+    pub usage: UsageConfig,
+    pub cache: CacheConfig,
+}
+
+impl Config {
+    /// The directory holding uhh's configuration, e.g. `~/.config/uhh` on Linux.
+    pub fn dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("uhh"))
+    }
+
+    /// Load the configuration file, or the defaults if there isn't one.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::dir().map(|dir| dir.join("config.toml")) else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&contents).wrap_err_with(|| format!("Invalid config in {}", path.display()))
+    }
+}
@@ -0,0 +1,103 @@
+use color_eyre::{Result, eyre::eyre};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The editor used when neither `$VISUAL` nor `$EDITOR` is set.
+#[cfg(unix)]
+const FALLBACK_EDITOR: &str = "vi";
+#[cfg(windows)]
+const FALLBACK_EDITOR: &str = "notepad";
+
+/// Edit `text` in the user's preferred editor and return the result.
+///
+/// The text is written to a temporary file with the given extension (so editors can pick a syntax
+/// mode), the editor from `$VISUAL` or `$EDITOR` is run on it, and the saved file is read back.
+/// The terminal must already be restored before calling this, as the editor takes it over.
+pub fn edit_text(text: &str, extension: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| FALLBACK_EDITOR.to_string());
+    edit_with(&editor, text, extension)
+}
+
+/// Edit `text` with the given editor command. See [`edit_text`].
+pub fn edit_with(editor: &str, text: &str, extension: &str) -> Result<String> {
+    let (path, mut file) = create_temp_file(extension)?;
+    file.write_all(text.as_bytes())?;
+    drop(file);
+
+    // Editors are commonly configured with arguments, e.g. `code --wait`.
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or(FALLBACK_EDITOR);
+
+    let status = Command::new(program).args(parts).arg(&path).status();
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    let status = status.map_err(|e| eyre!("Failed to launch {}: {}", program, e))?;
+    if !status.success() {
+        return Err(eyre!("{} exited with {}", program, status));
+    }
+
+    // Editors usually add a trailing newline, which would otherwise end up in the command.
+    Ok(edited?.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Create a new temporary file only the user can read, under a name nobody else could have
+/// created first. As the file is created rather than opened, a file or symlink planted at the
+/// name in a shared temporary directory makes this fail, rather than be written through.
+fn create_temp_file(extension: &str) -> io::Result<(PathBuf, File)> {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+    let mut attempts = 0;
+    loop {
+        let name = format!(
+            "uhh-{}-{}-{:08x}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            nanos,
+            extension
+        );
+        let path = std::env::temp_dir().join(name);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < 10 => attempts += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returns_the_saved_text() {
+        assert_eq!(edit_with("true", "ls -l\n", "sh").unwrap(), "ls -l");
+        assert!(edit_with("false", "ls -l", "sh").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn temp_files_are_private_and_new() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (path, _) = create_temp_file("txt").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let (other, _) = create_temp_file("txt").unwrap();
+        assert_ne!(path, other);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(other).unwrap();
+    }
+}
@@ -0,0 +1,741 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+use crate::app::SafetyStatus;
+use crate::extract;
+use crate::infer::InferenceEngine;
+use crate::shell::Shell;
+use crate::usage::Totals;
+
+/// How long a generated command may run for before it is killed and the task failed.
+const RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A set of tasks to evaluate models on, read from a TOML file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Suite {
+    /// The models to evaluate. If empty, the model uhh is configured with is used.
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(rename = "task")]
+    pub tasks: Vec<Task>,
+}
+
+impl Suite {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        let suite: Suite = toml::from_str(&contents)
+            .wrap_err_with(|| format!("Invalid eval suite in {}", path.display()))?;
+        // Check the patterns up front, rather than failing part way through a costly run.
+        for task in &suite.tasks {
+            for pattern in task.expect.iter().chain(&task.reject) {
+                Regex::new(pattern)
+                    .wrap_err_with(|| format!("Invalid pattern in task '{}'", task.name))?;
+            }
+            let Some(run) = &task.run else {
+                continue;
+            };
+            if let Some(stdout) = &run.stdout {
+                Regex::new(stdout)
+                    .wrap_err_with(|| format!("Invalid stdout pattern in task '{}'", task.name))?;
+            }
+            for path in run.files.keys().chain(&run.creates) {
+                if !is_contained(path) {
+                    return Err(eyre!(
+                        "Task '{}' has a path outside its scratch directory: {}",
+                        task.name,
+                        path
+                    ));
+                }
+            }
+        }
+        Ok(suite)
+    }
+}
+
+/// Whether a path stays inside the directory it is joined onto.
+fn is_contained(path: &str) -> bool {
+    use std::path::Component;
+
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// A request to generate a command for, and how to tell whether the command is right.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Task {
+    pub name: String,
+    pub prompt: String,
+    /// Patterns the command must match.
+    #[serde(default)]
+    pub expect: Vec<String>,
+    /// Patterns the command must not match.
+    #[serde(default)]
+    pub reject: Vec<String>,
+    /// Run the command, and check what it did.
+    pub run: Option<RunCheck>,
+}
+
+/// How to check a command by running it in a scratch directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunCheck {
+    /// Files to create in the scratch directory first, by path and contents.
+    pub files: BTreeMap<String, String>,
+    /// The exit code the command must exit with, 0 by default.
+    pub exit_code: i32,
+    /// A pattern the command's output must match, ignoring the trailing newline.
+    pub stdout: Option<String>,
+    /// Paths the command must create.
+    pub creates: Vec<String>,
+}
+
+/// How a model did on a task.
+#[derive(Debug)]
+pub struct Outcome {
+    pub task: String,
+    /// The generated command, or `None` if the request failed.
+    pub command: Option<String>,
+    /// Why the task failed, empty if it passed.
+    pub failures: Vec<String>,
+    pub latency: Duration,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// How a model did on a whole suite.
+#[derive(Debug)]
+pub struct ModelReport {
+    pub model: String,
+    /// The version of the command prompt.
+    pub prompt_version: String,
+    pub outcomes: Vec<Outcome>,
+    pub usage: Totals,
+    /// Whether some of the usage couldn't be priced, so the cost is an underestimate.
+    pub unpriced: bool,
+}
+
+impl ModelReport {
+    pub fn passed(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.passed())
+            .count()
+    }
+
+    pub fn pass_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+        self.passed() as f64 / self.outcomes.len() as f64
+    }
+
+    /// The mean time taken to generate a command.
+    pub fn mean_latency(&self) -> Duration {
+        let total: Duration = self.outcomes.iter().map(|outcome| outcome.latency).sum();
+        total
+            .checked_div(self.outcomes.len() as u32)
+            .unwrap_or_default()
+    }
+}
+
+/// Run every task in the suite against one model, with an engine set up for it.
+///
+/// The engine's meter should be fresh, as its session totals are reported as the model's usage.
+pub async fn run_model(suite: &Suite, model: &str, engine: &InferenceEngine) -> ModelReport {
+    let mut outcomes = Vec::new();
+    for task in &suite.tasks {
+        let started = Instant::now();
+        let result = engine.imagine_command(task.prompt.clone()).await;
+        let latency = started.elapsed();
+        let outcome = match result {
+            Ok(response) => {
+                let content = response
+                    .choices
+                    .first()
+                    .map_or("", |choice| choice.message.content.as_str());
+                let command = extract::extract(content, false).command;
+                Outcome {
+                    task: task.name.clone(),
+                    failures: check(task, &command, engine.shell()).await,
+                    command: Some(command),
+                    latency,
+                }
+            }
+            Err(e) => Outcome {
+                task: task.name.clone(),
+                command: None,
+                failures: vec![format!("Request failed: {}", e)],
+                latency,
+            },
+        };
+        outcomes.push(outcome);
+    }
+    ModelReport {
+        model: model.to_string(),
+        prompt_version: engine.templates().command.version.clone(),
+        outcomes,
+        usage: engine.meter().session(),
+        unpriced: engine.meter().has_unpriced(),
+    }
+}
+
+/// Check a generated command against a task, returning why it failed, if it did.
+pub async fn check(task: &Task, command: &str, shell: Shell) -> Vec<String> {
+    let mut failures = Vec::new();
+    for pattern in &task.expect {
+        // Patterns are checked when the suite is loaded.
+        if Regex::new(pattern).is_ok_and(|re| !re.is_match(command)) {
+            failures.push(format!("Does not match {}", pattern));
+        }
+    }
+    for pattern in &task.reject {
+        if Regex::new(pattern).is_ok_and(|re| re.is_match(command)) {
+            failures.push(format!("Matches {}", pattern));
+        }
+    }
+    if let Some(run) = &task.run
+        && let Err(e) = check_run(run, command, shell, &mut failures).await
+    {
+        failures.push(format!("Could not run the command: {}", e));
+    }
+    failures
+}
+
+/// Run a command in a scratch directory holding the fixture files, and check what it did.
+async fn check_run(
+    run: &RunCheck,
+    command: &str,
+    shell: Shell,
+    failures: &mut Vec<String>,
+) -> Result<()> {
+    let dir = Scratch::new()?;
+    for (path, contents) in &run.files {
+        let path = dir.path.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    let mut cmd = tokio::process::Command::from(shell.command(command));
+    cmd.current_dir(&dir.path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    // The command runs in a process group of its own, so anything it starts can be killed
+    // along with it.
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let child = cmd.spawn()?;
+    let group = child.id();
+    let output = tokio::time::timeout(RUN_TIMEOUT, child.wait_with_output()).await;
+    #[cfg(unix)]
+    if let Some(group) = group {
+        // SAFETY: kill has no memory safety requirements. The group outlives its leader for as
+        // long as anything in it is running, so this can't reach an unrelated process.
+        unsafe {
+            libc::kill(-(group as libc::pid_t), libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = group;
+    let output = output.map_err(|_| eyre!("Timed out after {}s", RUN_TIMEOUT.as_secs()))??;
+
+    match output.status.code() {
+        Some(code) if code == run.exit_code => {}
+        Some(code) => failures.push(format!("Exited with {}, not {}", code, run.exit_code)),
+        None => failures.push("Killed by a signal".to_string()),
+    }
+    if let Some(pattern) = &run.stdout {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if Regex::new(pattern).is_ok_and(|re| !re.is_match(stdout.trim_end())) {
+            failures.push(format!("Output does not match {}", pattern));
+        }
+    }
+    for path in &run.creates {
+        if !dir.path.join(path).exists() {
+            failures.push(format!("Did not create {}", path));
+        }
+    }
+    Ok(())
+}
+
+/// A temporary directory, removed when dropped.
+struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    /// Create a new, private directory, never reusing one that already exists.
+    fn new() -> Result<Self> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        let mut attempts = 0;
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "uhh-eval-{}-{}-{:08x}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed),
+                nanos
+            ));
+            let mut builder = std::fs::DirBuilder::new();
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempts < 10 => {
+                    attempts += 1;
+                }
+                Err(e) => {
+                    return Err(e).wrap_err_with(|| format!("Failed to create {}", path.display()));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A table comparing the models, one row each.
+pub fn summary(reports: &[ModelReport]) -> String {
+    let width = reports
+        .iter()
+        .map(|report| report.model.len())
+        .chain(["Model".len()])
+        .max()
+        .unwrap_or_default();
+    let mut table = format!(
+        "{:<width$}  {:>15}  {:>7}  {:>6}  {:>9}  {:>8}  {:>8}\n",
+        "Model", "Prompt", "Passed", "Rate", "Latency", "Tokens", "Cost"
+    );
+    for report in reports {
+        table.push_str(&format!(
+            "{:<width$}  {:>15}  {:>7}  {:>5.0}%  {:>8.2}s  {:>8}  {:>8}\n",
+            report.model,
+            report.prompt_version,
+            format!("{}/{}", report.passed(), report.outcomes.len()),
+            report.pass_rate() * 100.0,
+            report.mean_latency().as_secs_f64(),
+            report.usage.tokens(),
+            format!(
+                "${:.4}{}",
+                report.usage.cost,
+                if report.unpriced { "+" } else { "" }
+            ),
+        ));
+    }
+    table
+}
+
+/// Commands labelled dangerous or benign, for measuring safety checks, read from a TOML file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Corpus {
+    /// The models whose safety checks to measure. If empty, the model uhh is configured with is
+    /// used.
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Local analyzers to measure alongside the models: commands that read the command to check
+    /// on stdin, and exit with 0 if it is safe or 1 if it is dangerous.
+    #[serde(default)]
+    pub analyzers: Vec<String>,
+    #[serde(rename = "command")]
+    pub commands: Vec<LabelledCommand>,
+}
+
+impl Corpus {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("Invalid safety corpus in {}", path.display()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelledCommand {
+    pub command: String,
+    pub dangerous: bool,
+    /// Why the command is labelled the way it is.
+    #[serde(default)]
+    pub note: String,
+}
+
+/// What a safety check made of a labelled command.
+#[derive(Debug)]
+pub struct Verdict {
+    pub command: String,
+    pub dangerous: bool,
+    /// The review, or why the check failed to give one.
+    pub status: std::result::Result<SafetyStatus, String>,
+    pub latency: Duration,
+}
+
+impl Verdict {
+    /// Whether the command was held back, or `None` if the check failed. Anything short of a
+    /// clear approval is held back, as only an approval shows the command as safe.
+    pub fn flagged(&self) -> Option<bool> {
+        self.status
+            .as_ref()
+            .ok()
+            .map(|status| *status != SafetyStatus::Safe)
+    }
+
+    /// Whether the check got the command right. A failed check is neither right nor wrong.
+    pub fn correct(&self) -> Option<bool> {
+        self.flagged().map(|flagged| flagged == self.dangerous)
+    }
+}
+
+/// How a model or analyzer did on a corpus.
+#[derive(Debug)]
+pub struct SafetyReport {
+    /// The model or analyzer command.
+    pub reviewer: String,
+    /// The version of the safety prompt, for models.
+    pub prompt_version: Option<String>,
+    pub verdicts: Vec<Verdict>,
+    /// The tokens and cost, for models.
+    pub usage: Option<Totals>,
+    pub unpriced: bool,
+}
+
+impl SafetyReport {
+    fn count(&self, dangerous: bool, flagged: bool) -> usize {
+        self.verdicts
+            .iter()
+            .filter(|verdict| verdict.dangerous == dangerous && verdict.flagged() == Some(flagged))
+            .count()
+    }
+
+    /// The share of flagged commands that were dangerous, or `None` if nothing was flagged.
+    pub fn precision(&self) -> Option<f64> {
+        let (caught, false_alarms) = (self.count(true, true), self.count(false, true));
+        ratio(caught, caught + false_alarms)
+    }
+
+    /// The share of dangerous commands that were flagged, or `None` if none were dangerous.
+    pub fn recall(&self) -> Option<f64> {
+        let (caught, missed) = (self.count(true, true), self.count(true, false));
+        ratio(caught, caught + missed)
+    }
+
+    /// How many reviews were neither a clear approval nor a clear rejection.
+    pub fn unclear(&self) -> usize {
+        self.verdicts
+            .iter()
+            .filter(|verdict| verdict.status == Ok(SafetyStatus::Unknown))
+            .count()
+    }
+
+    /// How many checks failed, such as requests the API refused. These are left out of the
+    /// precision and recall, so an outage doesn't pass for a check that flags everything.
+    pub fn errors(&self) -> usize {
+        self.verdicts
+            .iter()
+            .filter(|verdict| verdict.status.is_err())
+            .count()
+    }
+
+    pub fn mean_latency(&self) -> Duration {
+        let total: Duration = self.verdicts.iter().map(|verdict| verdict.latency).sum();
+        total
+            .checked_div(self.verdicts.len() as u32)
+            .unwrap_or_default()
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+/// Run every command in the corpus through a model's safety check.
+///
+/// As with [`run_model`], the engine's meter should be fresh.
+pub async fn review_with_model(
+    corpus: &Corpus,
+    model: &str,
+    engine: &InferenceEngine,
+) -> SafetyReport {
+    let mut verdicts = Vec::new();
+    for labelled in &corpus.commands {
+        let started = Instant::now();
+        let status = match engine.inspect_command(labelled.command.clone()).await {
+            Ok(response) => Ok(response
+                .choices
+                .first()
+                .map_or(SafetyStatus::Unknown, |choice| {
+                    SafetyStatus::from_review(&choice.message.content)
+                })),
+            Err(e) => Err(e.to_string()),
+        };
+        verdicts.push(Verdict {
+            command: labelled.command.clone(),
+            dangerous: labelled.dangerous,
+            status,
+            latency: started.elapsed(),
+        });
+    }
+    SafetyReport {
+        reviewer: model.to_string(),
+        prompt_version: Some(engine.templates().safety.version.clone()),
+        verdicts,
+        usage: Some(engine.meter().session()),
+        unpriced: engine.meter().has_unpriced(),
+    }
+}
+
+/// Run every command in the corpus through a local analyzer.
+pub async fn review_with_analyzer(corpus: &Corpus, analyzer: &str, shell: Shell) -> SafetyReport {
+    let mut verdicts = Vec::new();
+    for labelled in &corpus.commands {
+        let started = Instant::now();
+        let status = match analyze(analyzer, &labelled.command, shell).await {
+            Ok(Some(0)) => Ok(SafetyStatus::Safe),
+            Ok(Some(1)) => Ok(SafetyStatus::Unsafe),
+            Ok(_) => Ok(SafetyStatus::Unknown),
+            Err(e) => Err(e.to_string()),
+        };
+        verdicts.push(Verdict {
+            command: labelled.command.clone(),
+            dangerous: labelled.dangerous,
+            status,
+            latency: started.elapsed(),
+        });
+    }
+    SafetyReport {
+        reviewer: analyzer.to_string(),
+        prompt_version: None,
+        verdicts,
+        usage: None,
+        unpriced: false,
+    }
+}
+
+/// Run an analyzer with the command on stdin, returning its exit code.
+async fn analyze(analyzer: &str, command: &str, shell: Shell) -> Result<Option<i32>> {
+    let mut cmd = tokio::process::Command::from(shell.command(analyzer));
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .wrap_err_with(|| format!("Failed to run {}", analyzer))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The analyzer may decide without reading everything.
+        let _ = stdin.write_all(command.as_bytes()).await;
+    }
+    let status = tokio::time::timeout(RUN_TIMEOUT, child.wait())
+        .await
+        .map_err(|_| eyre!("Timed out after {}s", RUN_TIMEOUT.as_secs()))??;
+    Ok(status.code())
+}
+
+/// A table comparing the models and analyzers, one row each.
+pub fn safety_summary(reports: &[SafetyReport]) -> String {
+    let width = reports
+        .iter()
+        .map(|report| report.reviewer.len())
+        .chain(["Reviewer".len()])
+        .max()
+        .unwrap_or_default();
+    let percent =
+        |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.0}%", v * 100.0));
+    let mut table = format!(
+        "{:<width$}  {:>15}  {:>9}  {:>6}  {:>7}  {:>6}  {:>9}  {:>8}\n",
+        "Reviewer", "Prompt", "Precision", "Recall", "Unclear", "Errors", "Latency", "Cost"
+    );
+    for report in reports {
+        table.push_str(&format!(
+            "{:<width$}  {:>15}  {:>9}  {:>6}  {:>7}  {:>6}  {:>8.2}s  {:>8}\n",
+            report.reviewer,
+            report.prompt_version.as_deref().unwrap_or("-"),
+            percent(report.precision()),
+            percent(report.recall()),
+            report.unclear(),
+            report.errors(),
+            report.mean_latency().as_secs_f64(),
+            report.usage.map_or("-".to_string(), |usage| format!(
+                "${:.4}{}",
+                usage.cost,
+                if report.unpriced { "+" } else { "" }
+            )),
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn checks_patterns_and_what_commands_do() {
+        let suite: Suite = toml::from_str(
+            r#"
+            [[task]]
+            name = "biggest"
+            prompt = "list files, biggest first"
+            expect = ['^ls\b', '(-S|--sort=size)']
+            reject = ['\brm\b']
+            [task.run]
+            files = { "small.txt" = "a", "dir/big.txt" = "a much bigger file" }
+            stdout = 'dir'
+            creates = ["dir/big.txt"]
+            "#,
+        )
+        .unwrap();
+        let task = &suite.tasks[0];
+
+        assert!(check(task, "ls -S", Shell::Sh).await.is_empty());
+        assert_eq!(
+            check(task, "ls", Shell::Sh).await,
+            ["Does not match (-S|--sort=size)"]
+        );
+        assert_eq!(
+            check(task, "rm -rf dir; ls -S", Shell::Sh).await,
+            [
+                "Does not match ^ls\\b",
+                "Matches \\brm\\b",
+                "Output does not match dir",
+                "Did not create dir/big.txt",
+            ]
+        );
+        assert_eq!(check(task, "ls -S missing", Shell::Sh).await.len(), 2);
+    }
+
+    #[test]
+    fn fixture_paths_stay_in_the_scratch_directory() {
+        let path = std::env::temp_dir().join(format!("uhh-eval-suite-{}.toml", std::process::id()));
+        let load = |run: &str| {
+            std::fs::write(
+                &path,
+                format!(
+                    "[[task]]\nname = \"t\"\nprompt = \"p\"\n[task.run]\n{}\n",
+                    run
+                ),
+            )
+            .unwrap();
+            Suite::load(&path).map(|_| ()).map_err(|e| e.to_string())
+        };
+
+        assert!(load(r#"files = { "dir/./a.txt" = "a" }"#).is_ok());
+        for run in [
+            r#"files = { "../x" = "a" }"#,
+            r#"files = { "/etc/x" = "a" }"#,
+            r#"files = { "dir/../../x" = "a" }"#,
+            r#"creates = ["../x"]"#,
+        ] {
+            assert!(
+                load(run)
+                    .unwrap_err()
+                    .contains("outside its scratch directory")
+            );
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn scratch_directories_are_never_reused() {
+        let first = Scratch::new().unwrap();
+        let second = Scratch::new().unwrap();
+        assert_ne!(first.path, second.path);
+        assert!(second.path.is_dir());
+        let path = second.path.clone();
+        drop(second);
+        assert!(!path.exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn processes_left_running_are_killed() {
+        let task: Task = toml::from_str("name = \"t\"\nprompt = \"p\"\n[run]").unwrap();
+        let pid_path = std::env::temp_dir().join(format!("uhh-eval-orphan-{}", std::process::id()));
+        let pid_file = pid_path.display().to_string();
+        check(
+            &task,
+            &format!("sleep 30 >/dev/null 2>&1 & echo $! > {}", pid_file),
+            Shell::Sh,
+        )
+        .await;
+
+        let pid = std::fs::read_to_string(&pid_path).unwrap();
+        let stat = format!("/proc/{}/stat", pid.trim());
+        // Once killed, the orphan is reaped by init, or at least left a zombie.
+        let mut gone = false;
+        for _ in 0..50 {
+            gone = std::fs::read_to_string(&stat)
+                .map_or(true, |stat| stat.split_whitespace().nth(2) == Some("Z"));
+            if gone {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(gone, "sleep {} is still running", pid.trim());
+        std::fs::remove_file(pid_path).unwrap();
+    }
+
+    #[test]
+    fn failed_checks_are_left_out_of_precision_and_recall() {
+        let verdict = |dangerous, status| Verdict {
+            command: "rm -rf /".into(),
+            dangerous,
+            status,
+            latency: Duration::from_secs(1),
+        };
+        let report = SafetyReport {
+            reviewer: "model".into(),
+            prompt_version: Some("v1".into()),
+            verdicts: vec![
+                verdict(true, Ok(SafetyStatus::Unsafe)),
+                verdict(true, Ok(SafetyStatus::Safe)),
+                verdict(false, Ok(SafetyStatus::Unknown)),
+                verdict(true, Err("Rate limited".into())),
+                verdict(false, Err("Rate limited".into())),
+            ],
+            usage: None,
+            unpriced: false,
+        };
+
+        assert_eq!(report.precision(), Some(0.5));
+        assert_eq!(report.recall(), Some(0.5));
+        assert_eq!((report.unclear(), report.errors()), (1, 2));
+        assert_eq!(report.verdicts[3].correct(), None);
+        let summary = safety_summary(&[report]);
+        let row = summary.lines().nth(1).unwrap();
+        assert_eq!(
+            row.split_whitespace().collect::<Vec<_>>(),
+            ["model", "v1", "50%", "50%", "1", "2", "1.00s", "-"]
+        );
+    }
+}
@@ -2,7 +2,7 @@ use color_eyre::eyre::OptionExt;
 use futures::{FutureExt, StreamExt};
 use ratatui::crossterm::event::Event as CrosstermEvent;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, task::JoinHandle};
 
 /// The frequency at which tick events are emitted.
 const TICK_FPS: f64 = 30.0;
@@ -36,15 +36,19 @@ pub enum AppEvent {
     /// Request completion from API.
     RequestCompletion(String),
     /// Completion response received.
-    CompletionResponse(String),
+    CompletionResponse { content: String, cached: bool },
     /// API request failed.
     CompletionError(String),
     /// Safety check response received.
-    SafetyCheckResponse(String),
+    SafetyCheckResponse { content: String, cached: bool },
     /// Safety check request failed.
     SafetyCheckError(String),
-    /// Execute command and replace current process.
+    /// Run a command, then exit with its status.
     ExecuteCommand(String),
+    /// Edit the focused pane in an external editor.
+    OpenEditor,
+    /// Static checks of a generated script finished.
+    ScriptChecked(String),
 }
 
 /// Terminal event handler.
@@ -54,15 +58,61 @@ pub struct EventHandler {
     pub sender: mpsc::UnboundedSender<Event>,
     /// Event receiver channel.
     receiver: mpsc::UnboundedReceiver<Event>,
+    /// The task reading terminal events, if it is running.
+    task: Option<JoinHandle<color_eyre::Result<()>>>,
+    /// Whether terminal events are read at all, or only sent through [`EventHandler::sender`].
+    reads_terminal: bool,
+}
+
+impl Default for EventHandler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EventHandler {
-    /// Constructs a new instance of [`EventHandler`] and spawns a new thread to handle events.
+    /// Constructs a new instance of [`EventHandler`].
+    ///
+    /// Terminal events are read on a new task once [`EventHandler::resume`] is called, which
+    /// [`crate::app::App::run`] does when it takes over the terminal.
     pub fn new() -> Self {
         let (sender, receiver) = mpsc::unbounded_channel();
-        let actor = EventTask::new(sender.clone());
-        tokio::spawn(async { actor.run().await });
-        Self { sender, receiver }
+        Self {
+            sender,
+            receiver,
+            task: None,
+            reads_terminal: true,
+        }
+    }
+
+    /// Constructs an [`EventHandler`] that never reads the terminal or emits ticks, so every event
+    /// comes from [`EventHandler::sender`], e.g. scripted key presses in tests.
+    pub fn headless() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver,
+            task: None,
+            reads_terminal: false,
+        }
+    }
+
+    /// Stop reading terminal events, e.g. while another program has control of the terminal.
+    ///
+    /// This waits for the event task to be dropped, so no input is consumed once it returns.
+    pub async fn pause(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            let _ = task.await;
+        }
+    }
+
+    /// Start reading terminal events, or start again after [`EventHandler::pause`].
+    pub fn resume(&mut self) {
+        if self.reads_terminal && self.task.is_none() {
+            let actor = EventTask::new(self.sender.clone());
+            self.task = Some(tokio::spawn(async { actor.run().await }));
+        }
     }
 
     /// Receives an event from the sender.
@@ -81,6 +131,11 @@ impl EventHandler {
             .ok_or_eyre("Failed to receive event")
     }
 
+    /// Receives an event if one is queued, without waiting for one.
+    pub fn try_next(&mut self) -> Option<Event> {
+        self.receiver.try_recv().ok()
+    }
+
     /// Queue an app event to be sent to the event receiver.
     ///
     /// This is useful for sending events to the event handler which will be processed by the next
@@ -0,0 +1,240 @@
+/// The command pulled out of a model response, and whatever surrounded it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Extraction {
+    /// The command or script to show in the command pane.
+    pub command: String,
+    /// Prose the model added around the command, for the review pane.
+    pub commentary: String,
+    /// Whether anything that might have been part of the command was dropped, e.g. a second
+    /// code block.
+    pub lossy: bool,
+}
+
+/// Language tags that mark a fenced block as shell code.
+const SHELL_TAGS: &[&str] = &[
+    "bash",
+    "sh",
+    "shell",
+    "zsh",
+    "fish",
+    "nu",
+    "nushell",
+    "console",
+    "powershell",
+    "pwsh",
+    "ps1",
+];
+
+/// Pull the command out of a model response.
+///
+/// Models don't always follow "no markdown" instructions, so this strips code fences, picks the
+/// command block and moves any prose around it into [`Extraction::commentary`]. With `script` set,
+/// everything from the shebang on is kept, rather than just the command line.
+pub fn extract(response: &str, script: bool) -> Extraction {
+    let response = response.trim();
+    if response.contains("```") {
+        return extract_fenced(response);
+    }
+
+    let lines: Vec<&str> = response.lines().collect();
+
+    let start = if script {
+        lines.iter().position(|line| line.starts_with("#!"))
+    } else {
+        // Skip introductions such as "Here is the command:".
+        lines
+            .iter()
+            .position(|line| !line.trim().is_empty() && !line.trim_end().ends_with(':'))
+    };
+    let Some(start) = start else {
+        return Extraction {
+            command: response.to_string(),
+            ..Default::default()
+        };
+    };
+
+    // Scripts run to the end. A command is a single line, unless it is continued onto the next,
+    // and anything after it is taken to be prose.
+    let end = if script {
+        lines.len()
+    } else {
+        lines[start..]
+            .iter()
+            .position(|line| !is_continued(line))
+            .map_or(lines.len(), |i| start + i + 1)
+    };
+
+    let command = lines[start..end].join("\n");
+    let commentary = [lines[..start].join("\n"), lines[end..].join("\n")]
+        .iter()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    Extraction {
+        command: clean_command(&command),
+        // Without fences there's no telling prose from more of the command for sure.
+        lossy: !commentary.is_empty(),
+        commentary,
+    }
+}
+
+/// Whether a line of a command carries on onto the next, e.g. after a trailing `\` or `|`.
+fn is_continued(line: &str) -> bool {
+    let line = line.trim_end();
+    ["\\", "|", "&&", "||", "(", "{"]
+        .iter()
+        .any(|end| line.ends_with(end))
+}
+
+/// Extract the command from a response with markdown code fences.
+fn extract_fenced(response: &str) -> Extraction {
+    let mut blocks: Vec<(String, Vec<&str>)> = Vec::new();
+    // Prose between blocks, kept as separate paragraphs.
+    let mut prose: Vec<Vec<&str>> = vec![Vec::new()];
+    let mut current: Option<(String, Vec<&str>)> = None;
+
+    for line in response.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (current.take(), fence) {
+            (None, Some(tag)) => current = Some((tag.trim().to_ascii_lowercase(), Vec::new())),
+            (None, None) => prose.last_mut().expect("prose is never empty").push(line),
+            (Some(block), Some(_)) => {
+                blocks.push(block);
+                prose.push(Vec::new());
+            }
+            (Some((tag, mut body)), None) => {
+                body.push(line);
+                current = Some((tag, body));
+            }
+        }
+    }
+    // An unclosed fence, e.g. from a truncated response, still holds the command.
+    let unclosed = current.is_some();
+    blocks.extend(current);
+
+    let chosen = blocks
+        .iter()
+        .position(|(tag, _)| SHELL_TAGS.contains(&tag.as_str()))
+        .unwrap_or(0);
+
+    let Some((_, body)) = blocks.get(chosen) else {
+        return Extraction {
+            command: response.to_string(),
+            ..Default::default()
+        };
+    };
+
+    let paragraphs = prose.iter().map(|lines| lines.join("\n"));
+    let other_blocks = blocks
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != chosen)
+        .map(|(_, (_, lines))| lines.join("\n"));
+    let commentary = paragraphs
+        .chain(other_blocks)
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    Extraction {
+        command: clean_command(&body.join("\n")),
+        commentary,
+        lossy: blocks.len() > 1 || unclosed,
+    }
+}
+
+/// Tidy up a single command: strip inline code backticks and a leading `$ ` prompt.
+fn clean_command(command: &str) -> String {
+    let command = command.trim();
+    if command.contains('\n') {
+        return command.to_string();
+    }
+    let command = command
+        .strip_prefix('`')
+        .and_then(|c| c.strip_suffix('`'))
+        .unwrap_or(command);
+    command.strip_prefix("$ ").unwrap_or(command).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_commands_are_untouched() {
+        let extraction = extract("ls -la --human-readable\n", false);
+        assert_eq!(extraction.command, "ls -la --human-readable");
+        assert_eq!(extraction.commentary, "");
+        assert!(!extraction.lossy);
+    }
+
+    #[test]
+    fn prose_around_a_fence_moves_to_commentary() {
+        let response = "Here is the command:\n```bash\nfind . -name '*.log' -delete\n```\nThis deletes every log file.";
+        let extraction = extract(response, false);
+        assert_eq!(extraction.command, "find . -name '*.log' -delete");
+        assert_eq!(
+            extraction.commentary,
+            "Here is the command:\n\nThis deletes every log file."
+        );
+        assert!(!extraction.lossy);
+    }
+
+    #[test]
+    fn multiple_blocks_prefer_shell_and_are_lossy() {
+        let response = "```json\n{}\n```\n```sh\necho hi\n```";
+        let extraction = extract(response, false);
+        assert_eq!(extraction.command, "echo hi");
+        assert_eq!(extraction.commentary, "{}");
+        assert!(extraction.lossy);
+    }
+
+    #[test]
+    fn unfenced_prose_is_split_off() {
+        let response = "Sure! Here you go:\n`$ du -sh *`\n\nThis shows the size of each entry.";
+        let extraction = extract(response, false);
+        assert_eq!(extraction.command, "du -sh *");
+        assert_eq!(
+            extraction.commentary,
+            "Sure! Here you go:\n\nThis shows the size of each entry."
+        );
+        assert!(extraction.lossy);
+    }
+
+    #[test]
+    fn unfenced_commands_end_with_their_line() {
+        let extraction = extract("ls -la\nThis lists the files.", false);
+        assert_eq!(extraction.command, "ls -la");
+        assert_eq!(extraction.commentary, "This lists the files.");
+        assert!(extraction.lossy);
+
+        let extraction = extract("cd foo\n\nmake", false);
+        assert_eq!(extraction.command, "cd foo");
+        assert_eq!(extraction.commentary, "make");
+        assert!(extraction.lossy);
+
+        let extraction = extract(
+            "find . -name '*.rs' |\n  xargs wc -l \\\n  --total=only",
+            false,
+        );
+        assert_eq!(
+            extraction.command,
+            "find . -name '*.rs' |\n  xargs wc -l \\\n  --total=only"
+        );
+        assert!(!extraction.lossy);
+    }
+
+    #[test]
+    fn scripts_keep_everything_from_the_shebang() {
+        let response = "Here's a script:\n#!/usr/bin/env bash\nset -euo pipefail\n\necho done";
+        let extraction = extract(response, true);
+        assert_eq!(
+            extraction.command,
+            "#!/usr/bin/env bash\nset -euo pipefail\n\necho done"
+        );
+        assert_eq!(extraction.commentary, "Here's a script:");
+    }
+}
@@ -0,0 +1,115 @@
+use ratatui::crossterm::event::{KeyCode, KeyModifiers};
+use std::collections::HashMap;
+
+use crate::keymap::KeyBinding;
+use crate::placeholder::{self, Kind, Placeholder};
+use crate::text::TextBuffer;
+
+/// What happened after a key press in the form.
+#[derive(Debug, PartialEq)]
+pub enum FormOutcome {
+    /// The form is still open.
+    Pending,
+    /// The user backed out.
+    Cancelled,
+    /// Every field is valid, giving the value for each placeholder.
+    Submitted(HashMap<String, String>),
+}
+
+/// A form for filling in the placeholders of a command before it runs.
+#[derive(Debug)]
+pub struct PlaceholderForm {
+    /// The command the placeholders came from.
+    pub command: String,
+    pub fields: Vec<(Placeholder, TextBuffer)>,
+    pub focused: usize,
+    /// A validation error or completion candidates for the focused field.
+    pub message: Option<String>,
+}
+
+impl PlaceholderForm {
+    /// A form for the placeholders in `command`, or `None` if it has none.
+    pub fn new(command: &str) -> Option<Self> {
+        let fields: Vec<_> = placeholder::parse(command)
+            .into_iter()
+            .map(|placeholder| {
+                let mut value = TextBuffer::new();
+                value.set_text(placeholder.default.clone().unwrap_or_default());
+                (placeholder, value)
+            })
+            .collect();
+        if fields.is_empty() {
+            return None;
+        }
+        Some(Self {
+            command: command.to_string(),
+            fields,
+            focused: 0,
+            message: None,
+        })
+    }
+
+    /// Handles a key press.
+    ///
+    /// Up and Down move between fields, Tab completes paths (or moves on, for other fields),
+    /// Enter submits and Esc cancels.
+    pub fn handle_key(&mut self, key: KeyBinding) -> FormOutcome {
+        self.message = None;
+        let last = self.fields.len() - 1;
+        let (field, value) = &mut self.fields[self.focused];
+        match key.code {
+            KeyCode::Esc => return FormOutcome::Cancelled,
+            KeyCode::Enter => return self.submit(),
+            KeyCode::Up | KeyCode::BackTab => self.focused = self.focused.saturating_sub(1),
+            KeyCode::Down => self.focused = (self.focused + 1).min(last),
+            KeyCode::Tab if field.kind == Kind::Path => {
+                let (completed, candidates) = placeholder::complete_path(value.text());
+                value.set_text(completed);
+                if candidates.len() > 1 {
+                    self.message = Some(candidates.join("  "));
+                }
+            }
+            KeyCode::Tab => self.focused = (self.focused + 1).min(last),
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                value.insert_char(c)
+            }
+            KeyCode::Backspace => value.backspace(),
+            KeyCode::Delete => value.delete(),
+            KeyCode::Left => value.move_left(),
+            KeyCode::Right => value.move_right(),
+            KeyCode::Home => value.move_home(),
+            KeyCode::End => value.move_end(),
+            _ => {}
+        }
+        FormOutcome::Pending
+    }
+
+    /// Validate every field, focusing the first invalid one.
+    fn submit(&mut self) -> FormOutcome {
+        let mut values = HashMap::new();
+        for (i, (placeholder, value)) in self.fields.iter().enumerate() {
+            if let Err(e) = placeholder.validate(value.text()) {
+                self.focused = i;
+                self.message = Some(e);
+                return FormOutcome::Pending;
+            }
+            values.insert(
+                placeholder.name.clone(),
+                expand_value(placeholder, value.text()),
+            );
+        }
+        FormOutcome::Submitted(values)
+    }
+}
+
+/// Expand a leading `~/` in paths, which quoting would otherwise stop the shell doing.
+fn expand_value(placeholder: &Placeholder, value: &str) -> String {
+    match (placeholder.kind, value.strip_prefix("~/"), dirs::home_dir()) {
+        (Kind::Path, Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+        _ => value.to_string(),
+    }
+}
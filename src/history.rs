@@ -0,0 +1,227 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::rank::Bm25;
+use crate::shell::Shell;
+
+/// How many of the most recent entries are read back from the history file.
+const MAX_ENTRIES: usize = 1000;
+
+/// A command that was run, and the prompt it was generated for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the command finished, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The shell the command ran in, as named in prompts.
+    pub shell: String,
+    pub prompt: String,
+    pub command: String,
+    /// The command's exit code, or `None` if it was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// The version of the prompt the command was generated with, or `None` if it didn't come
+    /// from the model, e.g. if it was recalled from a snippet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(
+        shell: Shell,
+        prompt: String,
+        command: String,
+        exit_code: Option<i32>,
+        prompt_version: Option<String>,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self {
+            timestamp,
+            shell: shell.to_string(),
+            prompt,
+            command,
+            exit_code,
+            prompt_version,
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// The commands uhh has run, stored as JSON lines in the data directory.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Where the history is stored, e.g. `~/.local/share/uhh/history.jsonl` on Linux.
+    pub fn path() -> Result<PathBuf> {
+        dirs::data_dir()
+            .map(|dir| dir.join("uhh").join("history.jsonl"))
+            .ok_or_else(|| eyre!("Could not find a data directory for the command history"))
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::path()?)
+    }
+
+    /// Load the most recent entries, skipping any lines that can't be parsed.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        let mut entries: Vec<HistoryEntry> = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        let excess = entries.len().saturating_sub(MAX_ENTRIES);
+        entries.drain(..excess);
+        Ok(Self { entries })
+    }
+
+    /// Append an entry to the history file.
+    pub fn append(entry: &HistoryEntry) -> Result<()> {
+        Self::append_to(&Self::path()?, entry)
+    }
+
+    pub fn append_to(path: &Path, entry: &HistoryEntry) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Commands are stored as they ran, secrets and all, so only the user may read them.
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(path)
+            .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            // Histories written before this was the case are made private too.
+            use std::os::unix::fs::PermissionsExt;
+
+            let metadata = file.metadata()?;
+            if metadata.permissions().mode() & 0o077 != 0 {
+                file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                    .wrap_err_with(|| format!("Failed to make {} private", path.display()))?;
+            }
+        }
+        writeln!(file, "{}", serde_json::to_string(entry)?)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Successful commands from the same shell whose prompts are most similar to `prompt`, most
+    /// similar first.
+    ///
+    /// Only the latest command for each prompt is considered, so a prompt that was run many
+    /// times doesn't crowd out the others.
+    pub fn similar(&self, prompt: &str, shell: Shell, limit: usize) -> Vec<&HistoryEntry> {
+        let shell = shell.to_string();
+        let mut candidates: Vec<&HistoryEntry> = Vec::new();
+        for entry in self.entries.iter().rev() {
+            let usable = entry.succeeded() && entry.shell == shell && !entry.prompt.is_empty();
+            if usable && !candidates.iter().any(|c| c.prompt == entry.prompt) {
+                candidates.push(entry);
+            }
+        }
+        let index = Bm25::new(candidates.iter().map(|entry| entry.prompt.as_str()));
+        index
+            .top(prompt, limit, 0.5)
+            .into_iter()
+            .map(|i| candidates[i])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(prompt: &str, command: &str, exit_code: i32) -> HistoryEntry {
+        HistoryEntry::new(
+            Shell::Bash,
+            prompt.into(),
+            command.into(),
+            Some(exit_code),
+            Some("v1".into()),
+        )
+    }
+
+    #[test]
+    fn finds_similar_successful_commands() {
+        let history = History {
+            entries: vec![
+                entry("convert video to mp3", "ffmpeg -i in.mp4 out.mp3", 0),
+                entry("convert video to gif", "ffmpeg -i in.mp4 out.gif", 1),
+                entry("list docker containers", "docker ps --all", 0),
+                entry("convert video to mp3", "ffmpeg -i talk.mkv talk.mp3", 0),
+            ],
+        };
+        let similar = history.similar("convert this video to gif", Shell::Bash, 3);
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].command, "ffmpeg -i talk.mkv talk.mp3");
+        assert!(history.similar("convert video", Shell::Fish, 3).is_empty());
+    }
+
+    #[test]
+    fn empty_histories_have_no_examples() {
+        assert!(
+            History::default()
+                .similar("list files", Shell::Bash, 3)
+                .is_empty()
+        );
+
+        let path = std::env::temp_dir().join(format!("uhh-history-{}.jsonl", std::process::id()));
+        let history = History::load_from(&path).unwrap();
+        assert!(history.entries.is_empty());
+        assert!(history.similar("list files", Shell::Bash, 3).is_empty());
+    }
+
+    #[test]
+    fn leaves_out_failed_commands() {
+        let mut killed = entry("compress the logs", "tar czf logs.tgz logs", 0);
+        killed.exit_code = None;
+        let history = History {
+            entries: vec![
+                entry("compress the logs", "zip -r logs.zip logs", 0),
+                entry("compress the logs", "tar cf logs.tar logs", 2),
+                killed,
+                entry("compress the photos", "tar czf photos.tgz photos", 127),
+            ],
+        };
+        let similar = history.similar("compress the logs", Shell::Bash, 3);
+        let commands: Vec<&str> = similar.iter().map(|entry| entry.command.as_str()).collect();
+        assert_eq!(commands, ["zip -r logs.zip logs"]);
+        assert!(history.similar("photos", Shell::Bash, 3).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_user_can_read_the_history() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path =
+            std::env::temp_dir().join(format!("uhh-history-mode-{}.jsonl", std::process::id()));
+        let mode = || std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        let _ = std::fs::remove_file(&path);
+        History::append_to(&path, &entry("a", "true", 0)).unwrap();
+        assert_eq!(mode(), 0o600);
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        History::append_to(&path, &entry("b", "true", 0)).unwrap();
+        assert_eq!(mode(), 0o600);
+        std::fs::remove_file(path).unwrap();
+    }
+}
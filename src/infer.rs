@@ -1,8 +1,22 @@
-use color_eyre::Result;
-use reqwest::Client;
+use color_eyre::{Result, eyre::WrapErr};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
 
-#[derive(Debug, Serialize)]
+use crate::audit::{Audit, Exchange};
+use crate::cache::Cache;
+use crate::history::History;
+use crate::inputs::Inputs;
+use crate::prompts::{Templates, Vars};
+use crate::redact::{Redactor, Secrets};
+use crate::shell::Shell;
+use crate::transport::{HttpTransport, Transport};
+use crate::usage::{Meter, Usage};
+
+/// How many past commands are shown to the model as examples.
+const EXAMPLES: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompletionRequest {
     pub model: String,
     pub messages: Vec<Message>,
@@ -10,7 +24,7 @@ pub struct CompletionRequest {
     pub temperature: Option<f32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
     pub content: String,
@@ -19,6 +33,11 @@ pub struct Message {
 #[derive(Debug, Deserialize)]
 pub struct CompletionResponse {
     pub choices: Vec<Choice>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    /// Whether the response came from the cache rather than the provider.
+    #[serde(skip)]
+    pub cached: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,12 +47,25 @@ pub struct Choice {
 
 #[derive(Clone, Debug)]
 pub struct InferenceEngine {
-    client: Client,
-    api_key: String,
-    base_url: String,
+    transport: Arc<dyn Transport>,
     model_ident: String,
-    input: Option<String>,
-    output: Option<String>,
+    inputs: Inputs,
+    /// What the input files hold, worked out once up front.
+    input_details: Option<String>,
+    outputs: Vec<String>,
+    shell: Shell,
+    history: History,
+    redactor: Redactor,
+    audit: Audit,
+    meter: Meter,
+    cache: Cache,
+    /// Whether to ask the model even if a response is cached, to get a different answer.
+    regenerate: bool,
+    templates: Arc<Templates>,
+    /// A sample of the data piped into uhh, which the command will read.
+    stdin: Option<String>,
+    /// Extra context for the model, beyond the request itself.
+    context: Option<String>,
 }
 
 impl InferenceEngine {
@@ -41,57 +73,256 @@ impl InferenceEngine {
         api_key: String,
         base_url: String,
         model_ident: String,
-        input: Option<String>,
-        output: Option<String>,
+        inputs: Inputs,
+        outputs: Vec<String>,
+        shell: Shell,
     ) -> Result<Self> {
-        let client = Client::new();
-
         Ok(Self {
-            client,
-            api_key,
-            input,
-            output,
+            transport: Arc::new(HttpTransport::new(api_key, base_url)?),
+            input_details: inputs.details(),
+            inputs,
+            outputs,
             model_ident,
-            base_url,
+            shell,
+            history: History::default(),
+            redactor: Redactor::default(),
+            audit: Audit::default(),
+            meter: Meter::default(),
+            cache: Cache::default(),
+            regenerate: false,
+            templates: Arc::default(),
+            stdin: None,
+            context: None,
         })
     }
 
-    pub async fn completion(&self, request: CompletionRequest) -> Result<CompletionResponse> {
-        let url = format!("{}/chat/completions", self.base_url);
+    /// Send requests with the given transport, instead of straight to the API.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// The transport requests are sent with.
+    pub fn transport(&self) -> Arc<dyn Transport> {
+        self.transport.clone()
+    }
+
+    /// Use similar commands from the user's history as examples when generating commands, to
+    /// teach the model their conventions.
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Redact secrets with the given patterns, instead of just the built in ones.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Record exchanges with the provider in the given audit log.
+    pub fn with_audit(mut self, audit: Audit) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Track usage and enforce budgets with the given meter.
+    pub fn with_meter(mut self, meter: Meter) -> Self {
+        self.meter = meter;
+        self
+    }
+
+    /// Reuse responses to identical requests from the given cache.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Ask the model afresh rather than reusing cached responses, caching the new ones in their
+    /// place.
+    pub fn regenerating(mut self) -> Self {
+        self.regenerate = true;
+        self
+    }
+
+    /// Build system prompts from the given templates, instead of the built in ones.
+    pub fn with_templates(mut self, templates: Templates) -> Self {
+        self.templates = Arc::new(templates);
+        self
+    }
+
+    /// Ask for commands that read piped data, showing the model a sample of it.
+    pub fn with_stdin(mut self, sample: String) -> Self {
+        self.stdin = Some(sample);
+        self
+    }
+
+    /// Give the model extra context for every request, beyond the request itself.
+    pub fn with_context(mut self, context: String) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// The files the user gave to work on.
+    pub fn inputs(&self) -> &Inputs {
+        &self.inputs
+    }
+
+    /// The templates system prompts are built from.
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    /// The meter tracking this session's usage.
+    pub fn meter(&self) -> &Meter {
+        &self.meter
+    }
+
+    /// The most recent exchanges with the provider, newest first.
+    pub fn recent_exchanges(&self) -> Vec<Exchange> {
+        self.audit.recent()
+    }
+
+    /// Why the audit log couldn't be written to, if it couldn't.
+    pub fn audit_failure(&self) -> Option<String> {
+        self.audit.failure()
+    }
+
+    /// The shell commands are generated for.
+    pub fn shell(&self) -> Shell {
+        self.shell
+    }
+
+    /// Send a request to the provider.
+    ///
+    /// Secrets in the messages are replaced with placeholders before anything is sent, and put
+    /// back into the response once it arrives, so they never leave the machine.
+    pub async fn completion(&self, mut request: CompletionRequest) -> Result<CompletionResponse> {
+        let mut secrets = Secrets::default();
+        for message in &mut request.messages {
+            message.content = self.redactor.redact(&message.content, &mut secrets);
+        }
+        if !secrets.is_empty() {
+            request.messages.insert(0, Message {
+                role: "system".into(),
+                content: "Secrets have been replaced with placeholders such as __UHH_SECRET_1__. Where a secret is needed, use its placeholder exactly as written.".into(),
+            });
+        }
+
+        let key = Cache::key(&request);
+        if !self.regenerate
+            && let Some(body) = self.cache.get(&key)
+        {
+            let mut completion_response = self.parse_response(&body, &secrets)?;
+            completion_response.cached = true;
+            self.audit.record(Exchange {
+                timestamp: Exchange::timestamp_now(),
+                model: self.model_ident.clone(),
+                request,
+                status: None,
+                response: body,
+                duration_ms: 0,
+                usage: None,
+                redacted: secrets.len(),
+                cached: true,
+            });
+            return Ok(completion_response);
+        }
+
+        self.meter.check_budget()?;
 
-        let response = self
-            .client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+        let started = Instant::now();
+        let timestamp = Exchange::timestamp_now();
+        let result = self.transport.send(&request).await;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await?;
+        let (status, body) = match &result {
+            Ok(response) => (Some(response.status), response.body.clone()),
+            Err(e) => (None, e.to_string()),
+        };
+        let usage = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| serde_json::from_value(body.get("usage")?.clone()).ok());
+        self.audit.record(Exchange {
+            timestamp,
+            model: self.model_ident.clone(),
+            request,
+            status,
+            response: body,
+            duration_ms: started.elapsed().as_millis() as u64,
+            usage,
+            redacted: secrets.len(),
+            cached: false,
+        });
+
+        let response = result?;
+        if !response.is_success() {
             return Err(color_eyre::eyre::eyre!(
                 "API request failed with status {}: {}",
-                status,
-                text
+                response.status,
+                response.body
             ));
         }
 
-        let completion_response: CompletionResponse = response.json().await?;
+        let body = response.body;
+        let completion_response = self.parse_response(&body, &secrets)?;
+        if let Some(usage) = completion_response.usage {
+            self.meter.record(&self.model_ident, usage).await;
+        }
+        // The cache is only an optimisation, so failing to write it shouldn't fail the request.
+        let _ = self.cache.put(&key, &body);
+        Ok(completion_response)
+    }
+
+    /// Parse a response body, restoring any redacted secrets.
+    fn parse_response(&self, body: &str, secrets: &Secrets) -> Result<CompletionResponse> {
+        let mut completion_response: CompletionResponse =
+            serde_json::from_str(body).wrap_err("Invalid response from the API")?;
+        for choice in &mut completion_response.choices {
+            choice.message.content = secrets.restore(&choice.message.content);
+        }
         Ok(completion_response)
     }
 
     pub async fn imagine_command(&self, request: String) -> Result<CompletionResponse> {
-        let mut base_prompt = "You are system designed to emit bash commands, fulfilling the user's request. To achieve your goal, emit a single line command and only that command to achieve the user's request. When possible, use verbose command switches, to convey intent. You can safely assume whatever programs needed to achieve your goal are avaiable to you, such as jq ffmpeg, etc. When emitting your command, emit only the command, with no markdown formatting\n".to_owned();
+        let base_prompt = self.templates.command.render(&self.vars());
 
-        if let Some(i) = self.input.as_ref() {
-            base_prompt.push_str(&format!("An output path has been provided, it is {i}\n"));
+        let mut messages = vec![Message {
+            role: "system".into(),
+            content: base_prompt,
+        }];
+        // The most similar example goes last, nearest the request.
+        let examples = self.history.similar(&request, self.shell, EXAMPLES);
+        for example in examples.into_iter().rev() {
+            messages.push(Message {
+                role: "user".to_string(),
+                content: example.prompt.clone(),
+            });
+            messages.push(Message {
+                role: "assistant".to_string(),
+                content: example.command.clone(),
+            });
         }
+        messages.push(Message {
+            role: "user".to_string(),
+            content: request,
+        });
 
-        if let Some(o) = self.output.as_ref() {
-            base_prompt.push_str(&format!("An output path has been provided, it is {o}\n"));
-        }
+        let request = CompletionRequest {
+            model: self.model_ident.clone(),
+            messages,
+            max_tokens: Some(1000),
+            temperature: Some(0.7),
+        };
+
+        self.completion(request).await
+    }
+
+    /// Like [`InferenceEngine::imagine_command`], but for tasks too big for a one-liner.
+    ///
+    /// Scripts are always bash, as they carry their own shebang rather than running in the
+    /// user's shell.
+    pub async fn imagine_script(&self, request: String) -> Result<CompletionResponse> {
+        let base_prompt = self.templates.script.render(&self.vars());
 
         let request = CompletionRequest {
             model: self.model_ident.clone(),
@@ -105,25 +336,55 @@ impl InferenceEngine {
                     content: request,
                 },
             ],
-            max_tokens: Some(1000),
+            max_tokens: Some(4000),
             temperature: Some(0.7),
         };
 
         self.completion(request).await
     }
 
+    /// The values of the prompt template variables.
+    fn vars(&self) -> Vars {
+        self.vars_in(self.shell)
+    }
+
+    /// The values of the prompt template variables, for commands in `shell`.
+    fn vars_in(&self, shell: Shell) -> Vars {
+        Vars {
+            shell: shell.to_string(),
+            shell_notes: shell.prompt_notes().to_string(),
+            os: os_name().to_string(),
+            input: self.inputs.describe(),
+            input_count: self.inputs.count(),
+            input_details: self.input_details.clone(),
+            output: describe_outputs(&self.outputs),
+            stdin: self.stdin.clone(),
+            context: self.context.clone(),
+        }
+    }
+
     pub async fn inspect_command(&self, request: String) -> Result<CompletionResponse> {
+        self.inspect(request, self.shell).await
+    }
+
+    /// Like [`InferenceEngine::inspect_command`], but for a script, which is bash whatever the
+    /// user's shell.
+    pub async fn inspect_script(&self, script: String) -> Result<CompletionResponse> {
+        self.inspect(script, Shell::Bash).await
+    }
+
+    async fn inspect(&self, request: String, shell: Shell) -> Result<CompletionResponse> {
         let request = CompletionRequest {
             model: self.model_ident.clone(),
             messages: vec![
                 Message {
                     role: "system".into(),
-                    content: "The user is going to pass in a command. Your role is to inspect this for safety, evaluating whether or not the command could cause unexpected harm. Unexpected harm may be deleting or removing more files than intended. Your response should first lead with a Y for safe or N for unsafe. Your analysis should be concise, focussing on any caveats first and foremost".into()
+                    content: self.templates.safety.render(&self.vars_in(shell)),
                 },
                 Message {
                     role: "user".to_string(),
                     content: request,
-                }
+                },
             ],
             max_tokens: Some(1000),
             temperature: Some(0.7),
@@ -132,3 +393,115 @@ impl InferenceEngine {
         self.completion(request).await
     }
 }
+
+/// The output paths as the prompt describes them, as [`Inputs::describe`] does the inputs.
+fn describe_outputs(outputs: &[String]) -> Option<String> {
+    match outputs {
+        [] => None,
+        [output] => Some(output.clone()),
+        outputs => Some(
+            outputs
+                .iter()
+                .map(|output| format!("\n- {}", output))
+                .collect(),
+        ),
+    }
+}
+
+/// The name of the operating system, as it might appear in a prompt.
+fn os_name() -> &'static str {
+    match std::env::consts::OS {
+        "linux" => "Linux",
+        "macos" => "macOS",
+        "windows" => "Windows",
+        "freebsd" => "FreeBSD",
+        os => os,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::RawResponse;
+    use futures::future::BoxFuture;
+    use std::sync::Mutex;
+
+    /// Keeps every request it is sent, and answers each with a new command.
+    #[derive(Debug, Default)]
+    struct Capture {
+        requests: Mutex<Vec<CompletionRequest>>,
+    }
+
+    impl Capture {
+        fn system_prompt(&self) -> String {
+            let requests = self.requests.lock().unwrap();
+            requests.last().unwrap().messages[0].content.clone()
+        }
+    }
+
+    impl Transport for Capture {
+        fn send<'a>(
+            &'a self,
+            request: &'a CompletionRequest,
+        ) -> BoxFuture<'a, Result<RawResponse>> {
+            let mut requests = self.requests.lock().unwrap();
+            requests.push(request.clone());
+            let body = serde_json::json!({ "choices": [{ "message": {
+                "role": "assistant",
+                "content": format!("echo {}", requests.len()),
+            }}]});
+            Box::pin(async move {
+                Ok(RawResponse {
+                    status: 200,
+                    body: body.to_string(),
+                })
+            })
+        }
+    }
+
+    fn engine(transport: Arc<Capture>) -> InferenceEngine {
+        InferenceEngine::new(
+            String::new(),
+            String::new(),
+            "test/model".into(),
+            Inputs::default(),
+            Vec::new(),
+            Shell::Bash,
+        )
+        .unwrap()
+        .with_transport(transport)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn piped_samples_reach_the_prompt() {
+        let transport = Arc::new(Capture::default());
+        let engine = engine(transport.clone());
+        engine.imagine_command("errors only".into()).await.unwrap();
+        assert!(!transport.system_prompt().contains("standard input"));
+
+        let engine = engine.with_stdin("\nINFO started\nERROR disk full".into());
+        engine.imagine_command("errors only".into()).await.unwrap();
+        assert!(transport.system_prompt().contains(
+            "it must read standard input and fit on the end of their pipeline after a |, rather \
+             than naming an input file. Here is a sample of the data: \nINFO started\nERROR disk full"
+        ));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn context_reaches_the_prompt() {
+        let transport = Arc::new(Capture::default());
+        let engine = engine(transport.clone());
+        engine.imagine_script("back up".into()).await.unwrap();
+        assert!(!transport.system_prompt().contains("context"));
+
+        let engine = engine.with_context("The backups go to /mnt/nas".into());
+        engine.imagine_command("back up".into()).await.unwrap();
+        assert!(
+            transport
+                .system_prompt()
+                .contains("The user has provided this context: The backups go to /mnt/nas\n")
+        );
+        engine.imagine_script("back up".into()).await.unwrap();
+        assert!(transport.system_prompt().contains("/mnt/nas"));
+    }
+}
@@ -0,0 +1,244 @@
+use color_eyre::{Result, eyre::eyre};
+use std::path::{Path, PathBuf};
+
+use crate::sniff;
+
+/// How many file names are listed in the prompt before the rest are just counted.
+const MAX_LISTED: usize = 50;
+/// How many files are looked inside for the prompt, taking one of each extension.
+const MAX_SNIFFED: usize = 3;
+
+/// How an input was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    File,
+    /// A directory, standing for the files directly inside it.
+    Directory,
+    /// A pattern such as `*.mov`, expanded by uhh rather than the shell.
+    Glob,
+    /// A path that doesn't exist yet, passed on as given.
+    Missing,
+}
+
+/// One `--input`, and the files it resolved to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    pub given: String,
+    pub kind: InputKind,
+    pub files: Vec<PathBuf>,
+}
+
+impl Input {
+    pub fn resolve(given: &str) -> Result<Self> {
+        let path = Path::new(given);
+        let (kind, files) = if path.is_dir() {
+            (InputKind::Directory, dir_files(path)?)
+        } else if path.exists() {
+            (InputKind::File, vec![path.to_path_buf()])
+        } else if given.contains(['*', '?', '[']) {
+            let files: Vec<PathBuf> = glob::glob(given)
+                .map_err(|e| eyre!("Invalid glob {}: {}", given, e))?
+                .filter_map(|path| path.ok())
+                .collect();
+            if files.is_empty() {
+                return Err(eyre!("No files match {}", given));
+            }
+            (InputKind::Glob, files)
+        } else {
+            (InputKind::Missing, Vec::new())
+        };
+        Ok(Self {
+            given: given.to_string(),
+            kind,
+            files,
+        })
+    }
+
+    /// A file's path relative to the directory or the fixed start of the glob it came from,
+    /// for listing beneath the input.
+    pub fn short_name<'a>(&self, file: &'a Path) -> &'a Path {
+        let base: PathBuf = match self.kind {
+            InputKind::Directory => PathBuf::from(&self.given),
+            InputKind::Glob => Path::new(&self.given)
+                .components()
+                .take_while(|component| {
+                    !component
+                        .as_os_str()
+                        .to_string_lossy()
+                        .contains(['*', '?', '['])
+                })
+                .collect(),
+            InputKind::File | InputKind::Missing => PathBuf::new(),
+        };
+        file.strip_prefix(base).unwrap_or(file)
+    }
+}
+
+/// The files directly inside a directory, leaving out hidden ones.
+fn dir_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| eyre!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Every `--input` given, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inputs {
+    pub inputs: Vec<Input>,
+}
+
+impl Inputs {
+    /// Resolve inputs as given on the command line, expanding directories and globs.
+    pub fn resolve(given: &[String]) -> Result<Self> {
+        let inputs = given
+            .iter()
+            .map(|given| Input::resolve(given))
+            .collect::<Result<_>>()?;
+        Ok(Self { inputs })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Every file the inputs resolved to, without repeats.
+    pub fn files(&self) -> Vec<&Path> {
+        let mut files: Vec<&Path> = Vec::new();
+        for file in self.inputs.iter().flat_map(|input| &input.files) {
+            if !files.contains(&file.as_path()) {
+                files.push(file);
+            }
+        }
+        files
+    }
+
+    /// The inputs as the prompt describes them: just the path for a single file, as before
+    /// there could be several, or a line for each input with the files it resolved to.
+    pub fn describe(&self) -> Option<String> {
+        match self.inputs.as_slice() {
+            [] => None,
+            [input] if matches!(input.kind, InputKind::File | InputKind::Missing) => {
+                Some(input.given.clone())
+            }
+            inputs => {
+                let mut listed = 0;
+                let mut description = String::new();
+                for input in inputs {
+                    description.push_str("\n- ");
+                    description.push_str(&input.given);
+                    let kind = match input.kind {
+                        InputKind::File | InputKind::Missing => continue,
+                        InputKind::Directory => "a directory holding",
+                        InputKind::Glob => "a glob matching",
+                    };
+                    let names: Vec<String> = input
+                        .files
+                        .iter()
+                        .take(MAX_LISTED.saturating_sub(listed))
+                        .map(|file| file.display().to_string())
+                        .collect();
+                    listed += names.len();
+                    description.push_str(&format!(
+                        ", {} {} files: {}",
+                        kind,
+                        input.files.len(),
+                        names.join(", ")
+                    ));
+                    if names.len() < input.files.len() {
+                        description
+                            .push_str(&format!(" and {} more", input.files.len() - names.len()));
+                    }
+                }
+                Some(description)
+            }
+        }
+    }
+
+    /// How many files there are, when there is more than one to handle.
+    pub fn count(&self) -> Option<String> {
+        let count = self.files().len();
+        (count > 1).then(|| count.to_string())
+    }
+
+    /// What the input files hold, from a look inside one file of each extension.
+    pub fn details(&self) -> Option<String> {
+        let files = self.files();
+        if let [file] = files.as_slice() {
+            return sniff::sniff(file).ok().map(|summary| summary.to_string());
+        }
+        let mut extensions = Vec::new();
+        let summaries: Vec<String> = files
+            .into_iter()
+            .filter(|file| {
+                let extension = file.extension().map(|extension| extension.to_os_string());
+                let new = !extensions.contains(&extension);
+                extensions.push(extension);
+                new
+            })
+            .take(MAX_SNIFFED)
+            .filter_map(|file| {
+                let summary = sniff::sniff(file).ok()?;
+                Some(format!("{}: {}", file.display(), summary))
+            })
+            .collect();
+        (!summaries.is_empty()).then(|| summaries.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_files_directories_and_globs() {
+        let dir = std::env::temp_dir().join(format!("uhh-inputs-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("clips")).unwrap();
+        for name in ["clips/a.mov", "clips/b.mov", "clips/.hidden", "notes.txt"] {
+            std::fs::write(dir.join(name), "x").unwrap();
+        }
+        let given = |name: &str| dir.join(name).display().to_string();
+
+        let single = Inputs::resolve(&[given("notes.txt")]).unwrap();
+        assert_eq!(single.describe(), Some(given("notes.txt")));
+        assert_eq!(single.count(), None);
+
+        let inputs =
+            Inputs::resolve(&[given("clips"), given("clips/*.mov"), given("new.txt")]).unwrap();
+        let kinds: Vec<InputKind> = inputs.inputs.iter().map(|input| input.kind).collect();
+        assert_eq!(
+            kinds,
+            [InputKind::Directory, InputKind::Glob, InputKind::Missing]
+        );
+        assert_eq!(inputs.files().len(), 2);
+        let clip = dir.join("clips/a.mov");
+        assert_eq!(inputs.inputs[0].short_name(&clip), Path::new("a.mov"));
+        assert_eq!(inputs.inputs[1].short_name(&clip), Path::new("a.mov"));
+        assert_eq!(inputs.count(), Some("2".into()));
+        let clips = format!("{}, {}", given("clips/a.mov"), given("clips/b.mov"));
+        assert_eq!(
+            inputs.describe().unwrap(),
+            format!(
+                "\n- {}, a directory holding 2 files: {clips}\n- {}, a glob matching 2 files: {clips}\n- {}",
+                given("clips"),
+                given("clips/*.mov"),
+                given("new.txt"),
+            )
+        );
+        assert_eq!(
+            inputs.details().unwrap(),
+            format!(
+                "{}: text/plain, 1 bytes\nFirst lines:\nx",
+                given("clips/a.mov")
+            )
+        );
+
+        assert!(Inputs::resolve(&[given("*.wav")]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,499 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// Something the user can do with a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    SwitchPane,
+    Submit,
+    OpenEditor,
+    Undo,
+    Redo,
+    ToggleHelp,
+    MoveLeft,
+    MoveRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    DeleteBackward,
+    DeleteForward,
+    NormalMode,
+    InsertMode,
+    Append,
+    ToggleScriptMode,
+    SaveSnippet,
+    OpenSnippets,
+    ToggleDebug,
+    /// Removes a default binding.
+    Unbound,
+}
+
+impl Action {
+    /// A short description, shown in the help overlay.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::SwitchPane => "Switch between the input and command panes",
+            Action::Submit => "Generate a command, or run the generated command",
+            Action::OpenEditor => "Edit the focused pane in $EDITOR",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::ToggleHelp => "Show or hide this help",
+            Action::MoveLeft => "Move the cursor left",
+            Action::MoveRight => "Move the cursor right",
+            Action::LineStart => "Move to the start of the line",
+            Action::LineEnd => "Move to the end of the line",
+            Action::PageUp => "Scroll up a page",
+            Action::PageDown => "Scroll down a page",
+            Action::DeleteBackward => "Delete before the cursor",
+            Action::DeleteForward => "Delete under the cursor",
+            Action::NormalMode => "Enter normal mode",
+            Action::InsertMode => "Enter insert mode",
+            Action::Append => "Enter insert mode after the cursor",
+            Action::ToggleScriptMode => "Switch between generating commands and scripts",
+            Action::SaveSnippet => "Save the command to the snippet library",
+            Action::OpenSnippets => "Search the snippet library",
+            Action::ToggleDebug => "Show or hide the recent requests to the model",
+            Action::Unbound => "Nothing",
+        }
+    }
+}
+
+/// The editing mode. Without the vi keymap, the app is always in [`Mode::Insert`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Insert,
+    Normal,
+}
+
+/// A key together with its modifiers, e.g. `ctrl+c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is already reflected in the character itself, and terminals disagree on whether to
+        // report it. Control and alt combinations are case insensitive, as they were before.
+        match code {
+            KeyCode::Char(c) => {
+                let modifiers = modifiers - KeyModifiers::SHIFT;
+                let c = if modifiers.is_empty() {
+                    c
+                } else {
+                    c.to_ascii_lowercase()
+                };
+                Self {
+                    code: KeyCode::Char(c),
+                    modifiers,
+                }
+            }
+            code => Self { code, modifiers },
+        }
+    }
+
+    /// Whether this is a plain printable character, which would otherwise be typed into a pane.
+    pub fn is_printable(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_)) && self.modifiers.is_empty()
+    }
+}
+
+impl From<KeyEvent> for KeyBinding {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // A lone "+" is a key in its own right, so only split on "+" between other keys.
+        let (modifier_names, key) = match s.rsplit_once('+') {
+            Some((modifiers, "")) if modifiers.ends_with('+') || modifiers.is_empty() => {
+                (modifiers.trim_end_matches('+'), "+")
+            }
+            Some((modifiers, key)) => (modifiers, key),
+            None => ("", s),
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match name.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(eyre!("Unknown modifier '{}' in '{}'", name, s)),
+            };
+        }
+
+        let code = match key.to_ascii_lowercase().as_str() {
+            "esc" | "escape" => KeyCode::Esc,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "space" => KeyCode::Char(' '),
+            name if name.len() > 1 && name.starts_with('f') => name[1..]
+                .parse()
+                .map(KeyCode::F)
+                .map_err(|_| eyre!("Unknown key '{}' in '{}'", key, s))?,
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(eyre!("Unknown key '{}' in '{}'", key, s)),
+                }
+            }
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if self.modifiers.is_empty() => write!(f, "{}", c),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "{}", code),
+        }
+    }
+}
+
+/// The `[keys]` section of the config file.
+///
+/// Bindings map a key such as `"ctrl+q"` to an action such as `"quit"`, and are applied on top of
+/// the defaults. Bind a key to `"unbound"` to remove a default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+    /// Use a vi-style modal keymap, where Esc enters normal mode rather than quitting.
+    pub vi: bool,
+    /// Bindings for insert mode, which is the only mode without `vi`.
+    pub bindings: HashMap<String, Action>,
+    /// Bindings for vi normal mode.
+    pub normal: HashMap<String, Action>,
+}
+
+/// Maps key presses to actions, for each mode.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Whether the vi-style modal keymap is enabled.
+    pub vi: bool,
+    insert: HashMap<KeyBinding, Action>,
+    normal: HashMap<KeyBinding, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_config(&KeysConfig::default()).expect("default keymap is valid")
+    }
+}
+
+impl Keymap {
+    /// Builds the keymap from the defaults and the user's overrides.
+    pub fn from_config(config: &KeysConfig) -> Result<Self> {
+        let mut insert = parse_bindings(&[
+            ("ctrl+c", Action::Quit),
+            ("up", Action::SwitchPane),
+            ("down", Action::SwitchPane),
+            ("enter", Action::Submit),
+            ("ctrl+e", Action::OpenEditor),
+            ("ctrl+z", Action::Undo),
+            ("ctrl+y", Action::Redo),
+            // A printable key is only bound while the pane is empty, so "?" is still typed
+            // anywhere else in a prompt.
+            ("?", Action::ToggleHelp),
+            ("f1", Action::ToggleHelp),
+            ("left", Action::MoveLeft),
+            ("right", Action::MoveRight),
+            ("home", Action::LineStart),
+            ("end", Action::LineEnd),
+            ("pageup", Action::PageUp),
+            ("pagedown", Action::PageDown),
+            ("ctrl+t", Action::ToggleScriptMode),
+            ("ctrl+s", Action::SaveSnippet),
+            ("ctrl+o", Action::OpenSnippets),
+            ("f12", Action::ToggleDebug),
+            ("backspace", Action::DeleteBackward),
+            ("delete", Action::DeleteForward),
+        ])?;
+        let mut normal = HashMap::new();
+
+        if config.vi {
+            insert.insert(
+                KeyBinding::new(KeyCode::Esc, KeyModifiers::NONE),
+                Action::NormalMode,
+            );
+            normal = parse_bindings(&[
+                ("ctrl+c", Action::Quit),
+                ("q", Action::Quit),
+                ("j", Action::SwitchPane),
+                ("k", Action::SwitchPane),
+                ("up", Action::SwitchPane),
+                ("down", Action::SwitchPane),
+                ("enter", Action::Submit),
+                ("ctrl+e", Action::OpenEditor),
+                ("u", Action::Undo),
+                ("ctrl+r", Action::Redo),
+                ("?", Action::ToggleHelp),
+                ("f1", Action::ToggleHelp),
+                ("h", Action::MoveLeft),
+                ("l", Action::MoveRight),
+                ("left", Action::MoveLeft),
+                ("right", Action::MoveRight),
+                ("0", Action::LineStart),
+                ("$", Action::LineEnd),
+                ("pageup", Action::PageUp),
+                ("pagedown", Action::PageDown),
+                ("ctrl+u", Action::PageUp),
+                ("ctrl+d", Action::PageDown),
+                ("ctrl+t", Action::ToggleScriptMode),
+                ("ctrl+s", Action::SaveSnippet),
+                ("ctrl+o", Action::OpenSnippets),
+                ("f12", Action::ToggleDebug),
+                ("x", Action::DeleteForward),
+                ("i", Action::InsertMode),
+                ("a", Action::Append),
+            ])?;
+        } else {
+            insert.insert(
+                KeyBinding::new(KeyCode::Esc, KeyModifiers::NONE),
+                Action::Quit,
+            );
+        }
+
+        insert.extend(parse_bindings_config(&config.bindings)?);
+        normal.extend(parse_bindings_config(&config.normal)?);
+
+        Ok(Self {
+            vi: config.vi,
+            insert,
+            normal,
+        })
+    }
+
+    /// The action bound to a key press in the given mode, if any.
+    pub fn action(&self, mode: Mode, key: KeyBinding) -> Option<Action> {
+        let bindings = match mode {
+            Mode::Insert => &self.insert,
+            Mode::Normal => &self.normal,
+        };
+        bindings
+            .get(&key)
+            .copied()
+            .filter(|&action| action != Action::Unbound)
+    }
+
+    /// A key bound to the action in the given mode, for hints in the status line.
+    ///
+    /// Prefers keys that always work over printable ones, then the shortest to display.
+    pub fn first_key(&self, mode: Mode, action: Action) -> Option<KeyBinding> {
+        self.bindings(mode)
+            .into_iter()
+            .find(|(bound, _)| *bound == action)
+            .and_then(|(_, keys)| {
+                keys.into_iter()
+                    .min_by_key(|key| (key.is_printable(), key.to_string().len()))
+            })
+    }
+
+    /// All bindings for a mode, grouped by action, in a stable order for display.
+    pub fn bindings(&self, mode: Mode) -> Vec<(Action, Vec<KeyBinding>)> {
+        let bindings = match mode {
+            Mode::Insert => &self.insert,
+            Mode::Normal => &self.normal,
+        };
+        let mut grouped: Vec<(Action, Vec<KeyBinding>)> = Vec::new();
+        let mut sorted: Vec<_> = bindings
+            .iter()
+            .filter(|(_, action)| **action != Action::Unbound)
+            .collect();
+        sorted.sort_by_key(|(key, action)| (**action, key.to_string()));
+        for (key, action) in sorted {
+            match grouped.last_mut() {
+                Some((last, keys)) if last == action => keys.push(*key),
+                _ => grouped.push((*action, vec![*key])),
+            }
+        }
+        grouped
+    }
+}
+
+fn parse_bindings(bindings: &[(&str, Action)]) -> Result<HashMap<KeyBinding, Action>> {
+    bindings
+        .iter()
+        .map(|(key, action)| Ok((key.parse()?, *action)))
+        .collect()
+}
+
+/// Parse bindings from the config, failing if two of them are the same key written differently,
+/// e.g. `"ctrl+q"` and `"Control+Q"`, as which would win is down to chance.
+fn parse_bindings_config(
+    bindings: &HashMap<String, Action>,
+) -> Result<HashMap<KeyBinding, Action>> {
+    let mut keys: Vec<&String> = bindings.keys().collect();
+    keys.sort();
+    let mut parsed: HashMap<KeyBinding, (&String, Action)> = HashMap::new();
+    for key in keys {
+        let binding: KeyBinding = key
+            .parse()
+            .wrap_err("Invalid key binding in the [keys] config")?;
+        if let Some((other, _)) = parsed.insert(binding, (key, bindings[key])) {
+            return Err(eyre!(
+                "'{}' and '{}' are the same key, in the [keys] config",
+                other,
+                key
+            ));
+        }
+    }
+    Ok(parsed
+        .into_iter()
+        .map(|(binding, (_, action))| (binding, action))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> KeyBinding {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_keys_and_modifiers() {
+        assert_eq!(
+            key("ctrl+q"),
+            KeyBinding::new(KeyCode::Char('q'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(key("Control+Shift+Q"), key("ctrl+q"));
+        assert_eq!(
+            key("alt+enter"),
+            KeyBinding::new(KeyCode::Enter, KeyModifiers::ALT)
+        );
+        assert_eq!(
+            key("F12"),
+            KeyBinding::new(KeyCode::F(12), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            key("space"),
+            KeyBinding::new(KeyCode::Char(' '), KeyModifiers::NONE)
+        );
+        // Shift is folded into printable characters.
+        assert_eq!(
+            KeyBinding::new(KeyCode::Char('?'), KeyModifiers::SHIFT),
+            key("?")
+        );
+
+        for bad in ["hyper+a", "ctrl+", "fx", "ab", "ctrl+nope"] {
+            assert!(bad.parse::<KeyBinding>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn plus_is_a_key_of_its_own() {
+        assert_eq!(
+            key("+"),
+            KeyBinding::new(KeyCode::Char('+'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            key("ctrl++"),
+            KeyBinding::new(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            key("ctrl+alt++"),
+            KeyBinding::new(
+                KeyCode::Char('+'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )
+        );
+        assert_eq!(key("ctrl++").to_string(), "Ctrl++");
+    }
+
+    #[test]
+    fn config_overrides_defaults_and_rejects_duplicates() {
+        let config = KeysConfig {
+            bindings: HashMap::from([
+                ("ctrl+q".to_string(), Action::Quit),
+                ("esc".to_string(), Action::Unbound),
+            ]),
+            ..KeysConfig::default()
+        };
+        let keymap = Keymap::from_config(&config).unwrap();
+        assert_eq!(
+            keymap.action(Mode::Insert, key("ctrl+q")),
+            Some(Action::Quit)
+        );
+        assert_eq!(keymap.action(Mode::Insert, key("esc")), None);
+        assert_eq!(
+            keymap.action(Mode::Insert, key("?")),
+            Some(Action::ToggleHelp)
+        );
+
+        let config = KeysConfig {
+            bindings: HashMap::from([
+                ("ctrl+q".to_string(), Action::Quit),
+                ("Control+Q".to_string(), Action::Undo),
+            ]),
+            ..KeysConfig::default()
+        };
+        let error = Keymap::from_config(&config).unwrap_err();
+        assert!(error.to_string().contains("same key"), "{}", error);
+    }
+
+    #[test]
+    fn vi_mode_has_its_own_bindings() {
+        let emacs = Keymap::default();
+        assert_eq!(emacs.action(Mode::Insert, key("esc")), Some(Action::Quit));
+        assert_eq!(emacs.action(Mode::Normal, key("j")), None);
+
+        let vi = Keymap::from_config(&KeysConfig {
+            vi: true,
+            ..KeysConfig::default()
+        })
+        .unwrap();
+        assert_eq!(
+            vi.action(Mode::Insert, key("esc")),
+            Some(Action::NormalMode)
+        );
+        assert_eq!(vi.action(Mode::Insert, key("j")), None);
+        assert_eq!(vi.action(Mode::Normal, key("j")), Some(Action::SwitchPane));
+        assert_eq!(vi.action(Mode::Normal, key("?")), Some(Action::ToggleHelp));
+        assert_eq!(vi.action(Mode::Normal, key("i")), Some(Action::InsertMode));
+        assert_eq!(
+            vi.first_key(Mode::Normal, Action::ToggleHelp),
+            Some(key("f1"))
+        );
+    }
+}
@@ -0,0 +1,31 @@
+pub mod app;
+pub mod audit;
+pub mod browser;
+pub mod cache;
+pub mod config;
+pub mod editor;
+pub mod eval;
+pub mod event;
+pub mod extract;
+pub mod form;
+pub mod history;
+pub mod infer;
+pub mod inputs;
+pub mod keymap;
+pub mod mock;
+pub mod pipe;
+pub mod placeholder;
+pub mod prompts;
+pub mod rank;
+pub mod redact;
+pub mod script;
+pub mod shell;
+pub mod sniff;
+pub mod snippets;
+pub mod terminal;
+pub mod text;
+pub mod theme;
+pub mod transport;
+pub mod ui;
+pub mod undo;
+pub mod usage;
@@ -1,41 +1,581 @@
-use clap::Parser;
-use infer::InferenceEngine;
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::WrapErr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::app::App;
+use uhh::app::{App, SafetyStatus};
+use uhh::audit::Audit;
+use uhh::cache::Cache;
+use uhh::config::Config;
+use uhh::eval::{self, Corpus, Suite};
+use uhh::history::History;
+use uhh::infer::InferenceEngine;
+use uhh::inputs::Inputs;
+use uhh::keymap::Keymap;
+use uhh::pipe::Piped;
+use uhh::prompts::{PromptKind, Template, Templates};
+use uhh::redact::Redactor;
+use uhh::shell::Shell;
+use uhh::snippets::{Library, PACK_FORMAT, Snippet, SnippetPack};
+use uhh::terminal;
+use uhh::theme::Theme;
+use uhh::transport::{Recorder, Replayer};
+use uhh::usage::{Ledger, Meter};
 
-pub mod app;
-pub mod event;
-pub mod infer;
-pub mod ui;
+const OPENROUTER_URL: &str = "https://openrouter.ai/api/v1";
 
 #[derive(Parser)]
 #[command(name = "uhh")]
 #[command(about = "A CLI tool to build up complex CLI commands with LLMs using a TUI interface.")]
 #[command(version)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// What to ask for, to start generating straight away.
+    prompt: Option<String>,
+    /// A file, directory or quoted glob such as '*.mov' for the command to work on. Repeat it
+    /// for several inputs. Their contents are summarised for the model.
     #[arg(short, long)]
-    input: Option<String>,
+    input: Vec<String>,
+    /// Where the command should write its results. Repeat it for several outputs.
     #[arg(short, long)]
-    output: Option<String>,
+    output: Vec<String>,
+    /// Background for the model to bear in mind, such as the project or machine the command is
+    /// for.
+    #[arg(long)]
+    context: Option<String>,
     #[arg(long)]
     dry_run: bool,
+    /// Generate a bash script to save, for tasks too big for a single command.
+    #[arg(long)]
+    script: bool,
+    /// The shell to generate commands for, and run them with. Detected from $SHELL by default.
+    #[arg(long, value_enum)]
+    shell: Option<Shell>,
+    /// Always ask the model, rather than reusing a cached response to the same request.
+    #[arg(long)]
+    no_cache: bool,
+    /// Record every request and response to a cassette file, for replaying later.
+    #[arg(long, value_name = "CASSETTE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Answer requests from a recorded cassette file, instead of calling the API.
+    #[arg(long, value_name = "CASSETTE")]
+    replay: Option<PathBuf>,
+    /// The OpenAI compatible API to send requests to, e.g. a local uhh-mock-server.
+    #[arg(long, default_value = OPENROUTER_URL)]
+    base_url: String,
+    /// The environment variable holding the API key for --base-url. OPENROUTER_API_KEY is only
+    /// sent to OpenRouter, so other APIs that need a key have to name one.
+    #[arg(long, value_name = "VAR")]
+    api_key_env: Option<String>,
+    /// The model to generate commands with.
+    #[arg(long, default_value = "google/gemini-2.5-flash")]
+    model: String,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Save a command to the snippet library, to recall later without asking the model.
+    Save {
+        /// The name to save the snippet as. Replaces any snippet with the same name.
+        name: String,
+        /// The command to save.
+        #[arg(short, long)]
+        command: String,
+        /// The request that the command fulfils.
+        #[arg(short, long, default_value = "")]
+        prompt: String,
+        /// What the command does.
+        #[arg(short, long, default_value = "")]
+        explanation: String,
+        /// A tag to file the snippet under. Can be repeated.
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Show the tokens used and money spent today and this month.
+    Usage,
+    /// Run a suite of tasks against one or more models, and compare how they do.
+    ///
+    /// Commands checked by running them are run in a scratch directory, but can still do
+    /// anything a command can, so only run suites you trust against models you trust.
+    Eval {
+        /// A TOML file of tasks, and the models to run them against.
+        suite: PathBuf,
+    },
+    /// Measure how well models' safety checks, and any local analyzers, tell dangerous
+    /// commands from benign ones.
+    EvalSafety {
+        /// A TOML file of labelled commands, and the models and analyzers to check them with.
+        corpus: PathBuf,
+    },
+    /// Share snippets with a team as pack files.
+    #[command(subcommand)]
+    Pack(PackCommands),
+    /// Show or customise the system prompts sent to the model.
+    #[command(subcommand)]
+    Prompts(PromptsCommands),
+}
+
+#[derive(Subcommand)]
+enum PromptsCommands {
+    /// Show the prompts in use, with their versions and where any overrides live.
+    Show {
+        /// The prompt to show. Shows all of them by default.
+        #[arg(value_enum)]
+        kind: Option<PromptKind>,
+        /// Show the built in prompt, even if it has been overridden.
+        #[arg(long)]
+        default: bool,
+    },
+    /// Override a prompt, editing it in $EDITOR. Delete the file it is saved to, to go back to
+    /// the built in prompt.
+    Edit {
+        #[arg(value_enum)]
+        kind: PromptKind,
+    },
+}
+
+#[derive(Subcommand)]
+enum PackCommands {
+    /// Install a TOML or JSON snippet pack, so its snippets are offered before asking the model.
+    Import {
+        path: PathBuf,
+        /// Install the pack even if a newer version is already installed.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write your snippets to a pack file, as JSON if it ends in .json and TOML otherwise.
+    Export {
+        path: PathBuf,
+        /// The name of the pack.
+        #[arg(short, long)]
+        name: String,
+        /// The version of the pack.
+        #[arg(short, long, default_value = "1.0.0")]
+        version: String,
+        /// What the pack is for.
+        #[arg(short, long, default_value = "")]
+        description: String,
+        /// Only export snippets with this tag.
+        #[arg(short, long)]
+        tag: Option<String>,
+    },
+    /// List installed packs.
+    List,
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
-    let api_key = std::env::var("OPENROUTER_API_KEY")
-        .map_err(|_| color_eyre::eyre::eyre!("OPENROUTER_API_KEY environment variable not set"))?;
-    let base_url = "https://openrouter.ai/api/v1".to_string();
-    let model_name = "google/gemini-2.5-flash".into();
-
     let args = Args::parse();
 
-    let infer = InferenceEngine::new(api_key, base_url, model_name, args.input, args.output)?;
+    if let Some(command) = args.command {
+        return match command {
+            Commands::Save {
+                name,
+                command,
+                prompt,
+                explanation,
+                tags,
+            } => {
+                let mut library = Library::load()?;
+                library.add(Snippet {
+                    name: name.clone(),
+                    tags,
+                    prompt,
+                    command,
+                    explanation,
+                    pack: None,
+                });
+                library.save()?;
+                println!("Uhh: Saved snippet {}", name);
+                Ok(())
+            }
+            Commands::Usage => {
+                let ledger = Ledger::load_from(&Ledger::path()?)?;
+                let today = uhh::usage::today();
+                for (period, totals) in [
+                    ("Today", ledger.day(&today)),
+                    ("This month", ledger.month(&today)),
+                ] {
+                    println!(
+                        "{}: {} requests, {} prompt + {} completion tokens, ${:.4}",
+                        period,
+                        totals.requests,
+                        totals.prompt_tokens,
+                        totals.completion_tokens,
+                        totals.cost
+                    );
+                }
+                Ok(())
+            }
+            Commands::Pack(command) => run_pack_command(command),
+            Commands::Prompts(command) => run_prompts_command(command),
+            Commands::Eval { suite } => {
+                let shell = args.shell.unwrap_or_else(Shell::detect);
+                let api_key = api_key(false, &args.base_url, args.api_key_env.as_deref())?;
+                run_eval(&suite, api_key, &args.base_url, &args.model, shell).await
+            }
+            Commands::EvalSafety { corpus } => {
+                let shell = args.shell.unwrap_or_else(Shell::detect);
+                let api_key = api_key(false, &args.base_url, args.api_key_env.as_deref())?;
+                run_safety_eval(&corpus, api_key, &args.base_url, &args.model, shell).await
+            }
+        };
+    }
+
+    let api_key = api_key(
+        args.replay.is_some(),
+        &args.base_url,
+        args.api_key_env.as_deref(),
+    )?;
+    let config = Config::load()?;
+    let keymap = Keymap::from_config(&config.keys)?;
+    let theme = Theme::load(config.theme);
+    let redactor = Redactor::new(&config.redact.patterns)?;
+    let audit = Audit::new(config.audit.log_path()?);
+    let meter = Meter::new(config.usage, Some(Ledger::path()?));
+    // Cassettes should capture every request, so they bypass the cache.
+    let use_cache = !args.no_cache && args.record.is_none() && args.replay.is_none();
+    let cache_dir = Cache::default_dir().ok().filter(|_| use_cache);
+    let cache = Cache::new(cache_dir, &config.cache);
+
+    // Take any piped input before the terminal is set up, as the pipe is replaced with it.
+    let piped = Piped::from_stdin()?;
+
+    let mut infer = InferenceEngine::new(
+        api_key,
+        args.base_url,
+        args.model,
+        Inputs::resolve(&args.input)?,
+        args.output,
+        args.shell.unwrap_or_else(Shell::detect),
+    )?
+    .with_history(History::load().unwrap_or_default())
+    .with_templates(Templates::load()?)
+    .with_redactor(redactor)
+    .with_audit(audit)
+    .with_meter(meter)
+    .with_cache(cache);
+    if let Some(path) = args.record {
+        let recorder = Recorder::new(infer.transport(), path);
+        infer = infer.with_transport(Arc::new(recorder));
+    } else if let Some(path) = args.replay {
+        infer = infer.with_transport(Arc::new(Replayer::load(&path)?));
+    }
 
-    let terminal = ratatui::init();
-    let result = App::new(infer, args.dry_run).run(terminal).await;
-    ratatui::restore();
+    if let Some(piped) = &piped {
+        infer = infer.with_stdin(piped.sample());
+    }
+    if let Some(context) = args.context {
+        infer = infer.with_context(context);
+    }
+
+    let mut app = App::new(infer, args.dry_run, args.script, keymap, theme);
+    if let Some(prompt) = args.prompt {
+        app = app.with_prompt(prompt);
+    }
+    if let Some(piped) = piped {
+        app = app.with_piped(piped);
+    }
+    let terminal = terminal::init();
+    let result = app.run(terminal).await;
+    terminal::restore();
     result
 }
+
+/// The API key to send to `base_url`.
+///
+/// OpenRouter's key is only ever sent to OpenRouter, so a mock server or another provider never
+/// sees it, and other APIs get the key in `key_env` if one is named.
+fn api_key(replaying: bool, base_url: &str, key_env: Option<&str>) -> color_eyre::Result<String> {
+    let openrouter = base_url.starts_with("https://openrouter.ai/");
+    let key_env = match key_env {
+        Some(key_env) => key_env,
+        None if openrouter => "OPENROUTER_API_KEY",
+        // Other APIs, such as a local mock server, may not need a key.
+        None => return Ok(String::new()),
+    };
+    match std::env::var(key_env) {
+        Ok(key) => Ok(key),
+        // Replaying never touches the API.
+        Err(_) if replaying => Ok(String::new()),
+        Err(_) => Err(color_eyre::eyre::eyre!(
+            "{} environment variable not set",
+            key_env
+        )),
+    }
+}
+
+/// Builds an engine for each model being evaluated.
+struct EvalEngines {
+    api_key: String,
+    base_url: String,
+    shell: Shell,
+    config: Config,
+    redactor: Redactor,
+    audit: Audit,
+    templates: Templates,
+}
+
+impl EvalEngines {
+    fn new(api_key: String, base_url: &str, shell: Shell) -> color_eyre::Result<Self> {
+        let config = Config::load()?;
+        Ok(Self {
+            api_key,
+            base_url: base_url.to_string(),
+            shell,
+            redactor: Redactor::new(&config.redact.patterns)?,
+            audit: Audit::new(config.audit.log_path()?),
+            templates: Templates::load()?,
+            config,
+        })
+    }
+
+    fn engine(&self, model: &str) -> color_eyre::Result<InferenceEngine> {
+        // Each model gets a fresh meter, so its session totals are its own. The cache and the
+        // user's history are left out, so every model is measured on the same footing.
+        let meter = Meter::new(self.config.usage.clone(), Some(Ledger::path()?));
+        Ok(InferenceEngine::new(
+            self.api_key.clone(),
+            self.base_url.clone(),
+            model.to_string(),
+            Inputs::default(),
+            Vec::new(),
+            self.shell,
+        )?
+        .with_redactor(self.redactor.clone())
+        .with_audit(self.audit.clone())
+        .with_meter(meter)
+        .with_templates(self.templates.clone()))
+    }
+}
+
+/// The models to evaluate, falling back to the configured one.
+fn eval_models(models: &[String], default_model: &str) -> Vec<String> {
+    if models.is_empty() {
+        vec![default_model.to_string()]
+    } else {
+        models.to_vec()
+    }
+}
+
+async fn run_eval(
+    path: &Path,
+    api_key: String,
+    base_url: &str,
+    default_model: &str,
+    shell: Shell,
+) -> color_eyre::Result<()> {
+    let suite = Suite::load(path)?;
+    let engines = EvalEngines::new(api_key, base_url, shell)?;
+
+    let mut reports = Vec::new();
+    for model in eval_models(&suite.models, default_model) {
+        println!("Uhh: Evaluating {} on {} tasks", model, suite.tasks.len());
+        let report = eval::run_model(&suite, &model, &engines.engine(&model)?).await;
+        for outcome in &report.outcomes {
+            println!(
+                "  {} {} ({:.2}s): {}",
+                if outcome.passed() { "PASS" } else { "FAIL" },
+                outcome.task,
+                outcome.latency.as_secs_f64(),
+                outcome.command.as_deref().unwrap_or("no command")
+            );
+            for failure in &outcome.failures {
+                println!("       {}", failure);
+            }
+        }
+        reports.push(report);
+    }
+    println!();
+    print!("{}", eval::summary(&reports));
+    Ok(())
+}
+
+async fn run_safety_eval(
+    path: &Path,
+    api_key: String,
+    base_url: &str,
+    default_model: &str,
+    shell: Shell,
+) -> color_eyre::Result<()> {
+    let corpus = Corpus::load(path)?;
+    let engines = EvalEngines::new(api_key, base_url, shell)?;
+
+    let mut reports = Vec::new();
+    for model in eval_models(&corpus.models, default_model) {
+        println!(
+            "Uhh: Checking {} commands with {}",
+            corpus.commands.len(),
+            model
+        );
+        let engine = engines.engine(&model)?;
+        reports.push(eval::review_with_model(&corpus, &model, &engine).await);
+    }
+    for analyzer in &corpus.analyzers {
+        println!(
+            "Uhh: Checking {} commands with {}",
+            corpus.commands.len(),
+            analyzer
+        );
+        reports.push(eval::review_with_analyzer(&corpus, analyzer, shell).await);
+    }
+
+    for report in &reports {
+        let mistakes: Vec<_> = report
+            .verdicts
+            .iter()
+            .filter(|verdict| verdict.correct() == Some(false))
+            .collect();
+        if !mistakes.is_empty() {
+            println!();
+            println!("{} got {} wrong:", report.reviewer, mistakes.len());
+            for verdict in mistakes {
+                let label = match (verdict.dangerous, &verdict.status) {
+                    (_, Ok(SafetyStatus::Unknown)) => "unclear",
+                    (true, _) => "missed",
+                    (false, _) => "false alarm",
+                };
+                println!("  {:<11} {}", label, verdict.command);
+            }
+        }
+        let errors: Vec<_> = report
+            .verdicts
+            .iter()
+            .filter_map(|verdict| Some((&verdict.command, verdict.status.as_ref().err()?)))
+            .collect();
+        if !errors.is_empty() {
+            println!();
+            println!("{} failed to check {}:", report.reviewer, errors.len());
+            for (command, error) in errors {
+                println!("  {}", command);
+                println!("    {}", error);
+            }
+        }
+    }
+    println!();
+    print!("{}", eval::safety_summary(&reports));
+    Ok(())
+}
+
+fn run_prompts_command(command: PromptsCommands) -> color_eyre::Result<()> {
+    match command {
+        PromptsCommands::Show { kind, default } => {
+            let templates = if default {
+                Templates::default()
+            } else {
+                Templates::load()?
+            };
+            let kinds = kind.map_or(PromptKind::ALL.to_vec(), |kind| vec![kind]);
+            for (i, kind) in kinds.into_iter().enumerate() {
+                let template = templates.get(kind);
+                if i > 0 {
+                    println!();
+                }
+                match &template.path {
+                    Some(path) => println!("# {} {} ({})", kind, template.version, path.display()),
+                    None => println!("# {} {} (built in)", kind, template.version),
+                }
+                print!("{}", template.text);
+            }
+        }
+        PromptsCommands::Edit { kind } => {
+            let path = Templates::path(kind)
+                .ok_or_else(|| color_eyre::eyre::eyre!("Could not find a config directory"))?;
+            let current = Templates::load()?.get(kind).text.clone();
+            let mut text = current.clone();
+            let edited = loop {
+                let edited = uhh::editor::edit_text(&text, "txt")? + "\n";
+                match Template::check(&edited) {
+                    Ok(()) => break edited,
+                    Err(e) => {
+                        eprintln!(
+                            "Uhh: {}. Press Enter to edit it again, or Ctrl+C to give up",
+                            e
+                        );
+                        std::io::stdin().read_line(&mut String::new())?;
+                        text = edited;
+                    }
+                }
+            };
+            if edited == current {
+                println!("Uhh: The {} prompt is unchanged", kind);
+                return Ok(());
+            }
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, &edited)
+                .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+            let template = Template::from_file(&path)?;
+            println!(
+                "Uhh: Saved the {} prompt, version {}, to {}",
+                kind,
+                template.version,
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_pack_command(command: PackCommands) -> color_eyre::Result<()> {
+    match command {
+        PackCommands::Import { path, force } => {
+            let pack = SnippetPack::read(&path)?;
+            let installed = Library::load()?.install_pack(&pack, force)?;
+            println!(
+                "Uhh: Installed {} {} ({} snippets) to {}",
+                pack.name,
+                pack.version,
+                pack.snippets.len(),
+                installed.display()
+            );
+        }
+        PackCommands::Export {
+            path,
+            name,
+            version,
+            description,
+            tag,
+        } => {
+            let snippets: Vec<Snippet> = Library::load()?
+                .snippets
+                .into_iter()
+                .filter(|snippet| tag.as_ref().is_none_or(|tag| snippet.tags.contains(tag)))
+                .collect();
+            let count = snippets.len();
+            SnippetPack {
+                format: PACK_FORMAT,
+                name,
+                version,
+                description,
+                snippets,
+                path: None,
+            }
+            .write(&path)?;
+            println!("Uhh: Exported {} snippets to {}", count, path.display());
+        }
+        PackCommands::List => {
+            let library = Library::load()?;
+            for warning in &library.warnings {
+                eprintln!("Uhh: Skipped {}", warning);
+            }
+            if library.packs.is_empty() {
+                println!("No packs installed in {}", Library::packs_dir()?.display());
+            }
+            for pack in &library.packs {
+                println!(
+                    "{} {} ({} snippets) {}",
+                    pack.name,
+                    pack.version,
+                    pack.snippets.len(),
+                    pack.description
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,405 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::infer::CompletionRequest;
+
+/// The largest request the mock server will read, to keep a bad client from exhausting memory.
+const MAX_REQUEST: usize = 1 << 20;
+
+/// A scripted response, from a `[[response]]` table in a mock script.
+///
+/// A response is used for requests that match all of its patterns. The first matching response
+/// in the script wins.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockResponse {
+    /// Only answer requests whose last message, the prompt or command, contains this.
+    #[serde(rename = "match")]
+    pub pattern: Option<String>,
+    /// Only answer requests whose first message, the system prompt, contains this.
+    pub system: Option<String>,
+    /// The message content to reply with, or the error message for error statuses.
+    pub content: String,
+    pub status: u16,
+    /// The `Retry-After` header to send, in seconds.
+    pub retry_after: Option<u64>,
+    /// How long to wait before replying, in milliseconds.
+    pub delay_ms: u64,
+    /// Reply with server-sent events, one word at a time, as a streaming provider would.
+    pub stream: bool,
+    /// How long to wait between streamed events, in milliseconds.
+    pub chunk_delay_ms: u64,
+    /// Reply with this body as is, instead of a completion, e.g. to send a malformed payload.
+    pub body: Option<String>,
+    /// Only answer this many requests, then leave later requests to the responses after it.
+    pub times: Option<usize>,
+}
+
+impl Default for MockResponse {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            system: None,
+            content: String::new(),
+            status: 200,
+            retry_after: None,
+            delay_ms: 0,
+            stream: false,
+            chunk_delay_ms: 0,
+            body: None,
+            times: None,
+        }
+    }
+}
+
+impl MockResponse {
+    fn matches(&self, request: &CompletionRequest) -> bool {
+        let contains = |message: Option<&crate::infer::Message>, pattern: &Option<String>| {
+            pattern.as_ref().is_none_or(|pattern| {
+                message.is_some_and(|message| message.content.contains(pattern.as_str()))
+            })
+        };
+        contains(request.messages.last(), &self.pattern)
+            && contains(request.messages.first(), &self.system)
+    }
+}
+
+/// The responses a mock server gives, as read from a TOML script.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockScript {
+    #[serde(default, rename = "response")]
+    pub responses: Vec<MockResponse>,
+}
+
+impl Default for MockScript {
+    /// Approve every safety check, and answer every other request with the same command.
+    fn default() -> Self {
+        Self {
+            responses: vec![
+                MockResponse {
+                    system: Some("inspect this for safety".into()),
+                    content: "Y. The mock server approves of every command.".into(),
+                    ..MockResponse::default()
+                },
+                MockResponse {
+                    content: "echo 'Hello from the mock server'".into(),
+                    ..MockResponse::default()
+                },
+            ],
+        }
+    }
+}
+
+impl MockScript {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("Invalid mock script in {}", path.display()))
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    /// How many requests each response has answered.
+    used: Vec<usize>,
+    requests: usize,
+}
+
+/// A stand-in for the provider's chat completions API, for exercising error handling and
+/// loading states without a real provider.
+///
+/// Clones share the same state, so a test can keep a handle to a server it has spawned.
+#[derive(Debug, Clone)]
+pub struct MockServer {
+    script: Arc<MockScript>,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockServer {
+    pub fn new(script: MockScript) -> Self {
+        let state = MockState {
+            used: vec![0; script.responses.len()],
+            requests: 0,
+        };
+        Self {
+            script: Arc::new(script),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// How many completion requests the server has received.
+    pub fn requests(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .requests
+    }
+
+    /// Answer connections on `listener` until the task is dropped.
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                // A client hanging up early is its own problem.
+                let _ = server.handle(stream).await;
+            });
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let Some((head, body)) = read_request(&mut stream).await? else {
+            return Ok(());
+        };
+        let request_line = head.lines().next().unwrap_or_default();
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next(), parts.next().unwrap_or_default());
+        if method != Some("POST") || !path.ends_with("/chat/completions") {
+            return write_response(&mut stream, 404, None, &error_body(404, "Not found")).await;
+        }
+
+        let request: CompletionRequest = match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(e) => {
+                let body = error_body(400, &format!("Invalid request: {}", e));
+                return write_response(&mut stream, 400, None, &body).await;
+            }
+        };
+        let Some(response) = self.respond_to(&request) else {
+            // Not a server error, so the client doesn't retry a script that can never match.
+            let body = error_body(400, "No scripted response matches the request");
+            return write_response(&mut stream, 400, None, &body).await;
+        };
+
+        tokio::time::sleep(Duration::from_millis(response.delay_ms)).await;
+        if let Some(body) = &response.body {
+            return write_response(&mut stream, response.status, response.retry_after, body).await;
+        }
+        if !(200..300).contains(&response.status) {
+            let body = error_body(response.status, &response.content);
+            return write_response(&mut stream, response.status, response.retry_after, &body).await;
+        }
+        if response.stream {
+            return write_stream(&mut stream, &request, &response).await;
+        }
+        let body = completion_body(&request, &response.content);
+        write_response(&mut stream, response.status, None, &body).await
+    }
+
+    /// Pick the scripted response for a request, counting it against the response's limit.
+    fn respond_to(&self, request: &CompletionRequest) -> Option<MockResponse> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.requests += 1;
+        let index = self
+            .script
+            .responses
+            .iter()
+            .enumerate()
+            .position(|(i, response)| {
+                response.matches(request)
+                    && response.times.is_none_or(|times| state.used[i] < times)
+            })?;
+        state.used[index] += 1;
+        Some(self.script.responses[index].clone())
+    }
+}
+
+/// Read a request's head and body, or `None` if the client hung up without sending one.
+async fn read_request(stream: &mut TcpStream) -> Result<Option<(String, Vec<u8>)>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_REQUEST {
+            return Err(eyre!("Request head too large"));
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_REQUEST {
+        return Err(eyre!("Request body too large"));
+    }
+    let mut body = buffer.split_off(head_end);
+    while body.len() < length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Ok(Some((head, body)))
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    retry_after: Option<u64>,
+    body: &str,
+) -> Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        reason(status),
+        body.len()
+    );
+    if let Some(secs) = retry_after {
+        head.push_str(&format!("Retry-After: {}\r\n", secs));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Stream the content as server-sent events, one word at a time, ending the body by closing
+/// the connection.
+async fn write_stream(
+    stream: &mut TcpStream,
+    request: &CompletionRequest,
+    response: &MockResponse,
+) -> Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status)
+    );
+    stream.write_all(head.as_bytes()).await?;
+    for word in response.content.split_inclusive(' ') {
+        let chunk = serde_json::json!({
+            "model": request.model,
+            "choices": [{ "delta": { "role": "assistant", "content": word } }],
+        });
+        stream
+            .write_all(format!("data: {}\n\n", chunk).as_bytes())
+            .await?;
+        stream.flush().await?;
+        tokio::time::sleep(Duration::from_millis(response.chunk_delay_ms)).await;
+    }
+    stream.write_all(b"data: [DONE]\n\n").await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// A chat completion answering the request with `content`, with rough token counts.
+fn completion_body(request: &CompletionRequest, content: &str) -> String {
+    let words = |text: &str| text.split_whitespace().count() as u64;
+    let prompt_tokens: u64 = request.messages.iter().map(|m| words(&m.content)).sum();
+    serde_json::json!({
+        "id": "mock",
+        "model": request.model,
+        "choices": [{ "message": { "role": "assistant", "content": content } }],
+        "usage": { "prompt_tokens": prompt_tokens, "completion_tokens": words(content) },
+    })
+    .to_string()
+}
+
+/// An error body in the shape OpenRouter uses.
+fn error_body(status: u16, message: &str) -> String {
+    serde_json::json!({ "error": { "code": status, "message": message } }).to_string()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        402 => "Payment Required",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::Message;
+
+    fn request(system: &str, prompt: &str) -> CompletionRequest {
+        CompletionRequest {
+            model: "model".into(),
+            messages: vec![
+                Message {
+                    role: "system".into(),
+                    content: system.into(),
+                },
+                Message {
+                    role: "user".into(),
+                    content: prompt.into(),
+                },
+            ],
+            max_tokens: None,
+            temperature: None,
+        }
+    }
+
+    #[test]
+    fn picks_the_first_matching_response_until_it_runs_out() {
+        let script: MockScript = toml::from_str(
+            r#"
+            [[response]]
+            match = "disk"
+            status = 503
+            times = 1
+
+            [[response]]
+            match = "disk"
+            content = "df -h"
+            "#,
+        )
+        .unwrap();
+        let server = MockServer::new(script);
+        let disk = request("", "show disk usage");
+        assert_eq!(server.respond_to(&disk).unwrap().status, 503);
+        assert_eq!(server.respond_to(&disk).unwrap().content, "df -h");
+        assert_eq!(server.respond_to(&disk).unwrap().content, "df -h");
+        assert!(server.respond_to(&request("", "list files")).is_none());
+        assert_eq!(server.requests(), 4);
+
+        let server = MockServer::new(MockScript::default());
+        let safety = request("Your role is to inspect this for safety", "ls");
+        assert!(
+            server
+                .respond_to(&safety)
+                .unwrap()
+                .content
+                .starts_with("Y.")
+        );
+        assert!(
+            server
+                .respond_to(&disk)
+                .unwrap()
+                .content
+                .starts_with("echo")
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use color_eyre::Result;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How much of the piped data is shown to the model.
+const SAMPLE_BYTES: usize = 8192;
+const SAMPLE_LINES: usize = 40;
+/// How long to wait for a sample, so a slow stream, such as from `tail -f`, doesn't hold up the
+/// app.
+const SAMPLE_WAIT: Duration = Duration::from_secs(2);
+const CHUNK_BYTES: usize = 8192;
+/// How many chunks can be read ahead of the command, before the writer to the pipe is made to
+/// wait as it would be by a pipe.
+const CHUNKS_AHEAD: usize = 16;
+
+/// Data piped into uhh, as in `journalctl | uhh "only the errors"`.
+///
+/// A thread reads the pipe, so a sample can be shown to the model without waiting for the stream
+/// to end, and the whole stream, sample and all, can still be fed to the command when it runs.
+#[derive(Debug)]
+pub struct Piped {
+    /// Everything read so far.
+    read: Vec<u8>,
+    chunks: Receiver<Vec<u8>>,
+    ended: bool,
+}
+
+impl Piped {
+    /// If stdin is a pipe rather than the terminal, take the pipe and point stdin back at the
+    /// terminal, so the app, and the editor or command it runs, can read keys.
+    ///
+    /// Elsewhere than Unix stdin is left alone, as it was before piping was supported, so uhh
+    /// still starts from an editor task or CI.
+    pub fn from_stdin() -> Result<Option<Self>> {
+        if io::stdin().is_terminal() {
+            return Ok(None);
+        }
+        #[cfg(unix)]
+        {
+            let pipe = reopen_terminal()?;
+            Ok(Some(Self::new(pipe)))
+        }
+        #[cfg(not(unix))]
+        {
+            eprintln!("Uhh: Ignoring piped input, which is only supported on Unix");
+            Ok(None)
+        }
+    }
+
+    /// Read from a pipe, waiting a little for a sample of it.
+    pub fn new(mut reader: impl Read + Send + 'static) -> Self {
+        let (sender, chunks) = mpsc::sync_channel(CHUNKS_AHEAD);
+        std::thread::spawn(move || {
+            loop {
+                let mut chunk = vec![0; CHUNK_BYTES];
+                match reader.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => {
+                        chunk.truncate(read);
+                        if sender.send(chunk).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        let mut piped = Self {
+            read: Vec::new(),
+            chunks,
+            ended: false,
+        };
+        let deadline = Instant::now() + SAMPLE_WAIT;
+        while !piped.ended
+            && piped.read.len() < SAMPLE_BYTES
+            && piped.read.iter().filter(|&&byte| byte == b'\n').count() < SAMPLE_LINES
+        {
+            match piped
+                .chunks
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(chunk) => piped.read.extend(chunk),
+                Err(RecvTimeoutError::Disconnected) => piped.ended = true,
+                Err(RecvTimeoutError::Timeout) => break,
+            }
+        }
+        piped
+    }
+
+    /// The first lines of the data, to show the model what the command will be reading.
+    pub fn sample(&self) -> String {
+        let head = &self.read[..self.read.len().min(SAMPLE_BYTES)];
+        if head.is_empty() {
+            return if self.ended {
+                "(nothing, the input was empty)".into()
+            } else {
+                "(nothing has arrived yet)".into()
+            };
+        }
+        if head.contains(&0) {
+            return format!(
+                "binary data, {} bytes so far{}",
+                self.read.len(),
+                if self.ended { ", all of it" } else { "" }
+            );
+        }
+        let text = String::from_utf8_lossy(head);
+        let mut sample = text
+            .lines()
+            .take(SAMPLE_LINES)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !self.ended || head.len() < self.read.len() || text.lines().count() > SAMPLE_LINES {
+            sample.push_str("\n(this is just the start, more data follows)");
+        }
+        format!("\n{}", sample)
+    }
+
+    /// Write the whole stream to a writer, such as the command's stdin, ignoring a writer that
+    /// stops reading early as `head` does.
+    pub fn feed(self, mut writer: impl Write) -> io::Result<()> {
+        let result = std::iter::once(self.read)
+            .chain(self.chunks)
+            .try_for_each(|chunk| writer.write_all(&chunk));
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Move the pipe on stdin to a new descriptor, and put the terminal in its place.
+#[cfg(unix)]
+fn reopen_terminal() -> Result<std::fs::File> {
+    use color_eyre::eyre::WrapErr;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let terminal = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .wrap_err("Failed to open the terminal, which uhh needs when input is piped to it")?;
+    // SAFETY: dup returns a new descriptor, which the File takes ownership of, and dup2 only
+    // replaces stdin, which nothing else holds a handle to yet.
+    unsafe {
+        let pipe = libc::dup(libc::STDIN_FILENO);
+        if pipe < 0 {
+            return Err(io::Error::last_os_error()).wrap_err("Failed to take the piped input");
+        }
+        let pipe = std::fs::File::from_raw_fd(pipe);
+        if libc::dup2(terminal.as_raw_fd(), libc::STDIN_FILENO) < 0 {
+            return Err(io::Error::last_os_error()).wrap_err("Failed to reopen the terminal");
+        }
+        Ok(pipe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_the_start_and_feeds_everything() {
+        let data: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+        let piped = Piped::new(io::Cursor::new(data.clone().into_bytes()));
+
+        let sample = piped.sample();
+        assert!(sample.starts_with("\nline 1\nline 2\n"));
+        assert!(sample.contains("line 40\n"));
+        assert!(!sample.contains("line 41\n"));
+        assert!(sample.ends_with("more data follows)"));
+
+        let mut fed = Vec::new();
+        piped.feed(&mut fed).unwrap();
+        assert_eq!(fed, data.into_bytes());
+
+        let short = Piped::new(io::Cursor::new(b"a,b\n1,2\n".to_vec()));
+        assert_eq!(short.sample(), "\na,b\n1,2");
+    }
+}
@@ -0,0 +1,415 @@
+use std::{collections::HashMap, ops::Range, path::Path};
+
+use crate::shell::Shell;
+
+/// The type of value a placeholder accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Str,
+    Path,
+    Int,
+    Float,
+}
+
+impl Kind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "" | "str" | "string" => Some(Kind::Str),
+            "path" | "file" | "dir" => Some(Kind::Path),
+            "int" => Some(Kind::Int),
+            "float" | "number" => Some(Kind::Float),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Str => "text",
+            Kind::Path => "path",
+            Kind::Int => "integer",
+            Kind::Float => "number",
+        }
+    }
+}
+
+/// A typed placeholder in a command, such as `{{input_file:path}}` or `{{threads:int=4}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placeholder {
+    pub name: String,
+    pub kind: Kind,
+    pub default: Option<String>,
+}
+
+impl Placeholder {
+    /// Check a value entered for this placeholder.
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        if value.is_empty() {
+            return Err(format!("{} needs a value", self.name));
+        }
+        let valid = match self.kind {
+            Kind::Str | Kind::Path => true,
+            Kind::Int => value.parse::<i64>().is_ok(),
+            Kind::Float => value.parse::<f64>().is_ok(),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("{} must be a {}", self.name, self.kind.name()))
+        }
+    }
+}
+
+/// A placeholder's position in a command.
+struct Occurrence {
+    placeholder: Placeholder,
+    range: Range<usize>,
+}
+
+/// Find every placeholder in a command.
+///
+/// `{{...}}` sequences which aren't valid placeholders, such as Go templates in
+/// `docker ps --format '{{.Names}}'`, are left alone.
+fn occurrences(command: &str) -> Vec<Occurrence> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = command[offset..].find("{{").map(|i| offset + i) {
+        let Some(end) = command[start..].find("}}").map(|i| start + i + 2) else {
+            break;
+        };
+        match parse_placeholder(&command[start + 2..end - 2]) {
+            Some(placeholder) => {
+                found.push(Occurrence {
+                    placeholder,
+                    range: start..end,
+                });
+                offset = end;
+            }
+            None => offset = start + 2,
+        }
+    }
+    found
+}
+
+/// Parse the inside of `{{name:type=default}}`.
+fn parse_placeholder(inner: &str) -> Option<Placeholder> {
+    let (spec, default) = match inner.split_once('=') {
+        Some((spec, default)) => (spec, Some(default.to_string())),
+        None => (inner, None),
+    };
+    let (name, kind) = spec.split_once(':').unwrap_or((spec, ""));
+    let name = name.trim();
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid_name {
+        return None;
+    }
+    Some(Placeholder {
+        name: name.to_string(),
+        kind: Kind::parse(kind.trim())?,
+        default,
+    })
+}
+
+/// The distinct placeholders in a command, in the order they first appear.
+pub fn parse(command: &str) -> Vec<Placeholder> {
+    let mut placeholders: Vec<Placeholder> = Vec::new();
+    for occurrence in occurrences(command) {
+        if !placeholders
+            .iter()
+            .any(|p| p.name == occurrence.placeholder.name)
+        {
+            placeholders.push(occurrence.placeholder);
+        }
+    }
+    placeholders
+}
+
+/// The quotes a placeholder sits inside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    None,
+    Single,
+    Double,
+}
+
+/// The character that escapes the next one, in and outside of double quotes.
+fn escape_chars(shell: Shell) -> (Option<char>, Option<char>) {
+    match shell {
+        Shell::Sh | Shell::Bash | Shell::Zsh | Shell::Fish => (Some('\\'), Some('\\')),
+        // A backslash is literal in a bare word, and only escapes inside double quotes.
+        Shell::Nushell => (None, Some('\\')),
+        Shell::PowerShell => (Some('`'), Some('`')),
+    }
+}
+
+/// The quotes open at the end of `prefix`, following the dialect's escaping rules.
+fn quoting(prefix: &str, shell: Shell) -> Quoting {
+    let (bare_escape, double_escape) = escape_chars(shell);
+    let mut quoting = Quoting::None;
+    let mut chars = prefix.chars();
+    while let Some(c) = chars.next() {
+        quoting = match (quoting, c) {
+            (Quoting::None, c) if Some(c) == bare_escape => {
+                chars.next();
+                quoting
+            }
+            (Quoting::Double, c) if Some(c) == double_escape => {
+                chars.next();
+                quoting
+            }
+            // Only fish lets a single quoted string escape its quote.
+            (Quoting::Single, '\\') if shell == Shell::Fish => {
+                chars.next();
+                quoting
+            }
+            (Quoting::None, '\'') => Quoting::Single,
+            (Quoting::Single, '\'') => Quoting::None,
+            (Quoting::None, '"') => Quoting::Double,
+            (Quoting::Double, '"') => Quoting::None,
+            _ => quoting,
+        };
+    }
+    quoting
+}
+
+/// Escape a value for the middle of a double quoted string, where `$` and friends still expand.
+fn escape_in_double_quotes(value: &str, shell: Shell) -> Option<String> {
+    let special: &[char] = match shell {
+        Shell::Sh | Shell::Bash | Shell::Zsh => &['$', '`', '\\', '"'],
+        Shell::Fish => &['$', '\\', '"'],
+        // These expand far more inside double quotes, so they aren't handled.
+        Shell::Nushell | Shell::PowerShell => return None,
+    };
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Some(escaped)
+}
+
+/// Replace each placeholder with its value, quoted for the shell.
+///
+/// If the model already wrapped a placeholder in quotes, those are replaced too, so values are
+/// never quoted twice. A placeholder in the middle of a quoted string, as in
+/// `echo "Hello {{name}}!"`, is escaped for those quotes instead, and where that can't be done
+/// reliably for the dialect an error is returned, rather than a command that might run part of
+/// the value.
+pub fn substitute(
+    command: &str,
+    values: &HashMap<String, String>,
+    shell: Shell,
+) -> Result<String, String> {
+    let mut result = String::with_capacity(command.len());
+    let mut last = 0;
+    for Occurrence { placeholder, range } in occurrences(command) {
+        let Some(value) = values.get(&placeholder.name) else {
+            continue;
+        };
+        let mut range = range;
+        let before = command[..range.start].chars().next_back();
+        let after = command[range.end..].chars().next();
+        let quoting = quoting(&command[..range.start], shell);
+        let opening = match quoting {
+            Quoting::None => None,
+            Quoting::Single => Some('\''),
+            Quoting::Double => Some('"'),
+        };
+        // The quotes hold nothing but the placeholder.
+        let wrapped = opening.is_some()
+            && before == opening
+            && after == opening
+            && range.start > last
+            && self::quoting(&command[..range.start - 1], shell) == Quoting::None;
+        let quoted = match quoting {
+            _ if wrapped => {
+                range = range.start - 1..range.end + 1;
+                shell.quote(value)
+            }
+            Quoting::None => shell.quote(value),
+            // Close the quotes before the quoted value, and open them again after it.
+            Quoting::Single if !matches!(shell, Shell::Nushell | Shell::PowerShell) => {
+                format!("'{}'", shell.quote(value))
+            }
+            Quoting::Double if let Some(escaped) = escape_in_double_quotes(value, shell) => escaped,
+            Quoting::Single | Quoting::Double => {
+                return Err(format!(
+                    "{} is inside a quoted string, where it can't be filled in safely for {}",
+                    placeholder.name, shell
+                ));
+            }
+        };
+        result.push_str(&command[last..range.start]);
+        result.push_str(&quoted);
+        last = range.end;
+    }
+    result.push_str(&command[last..]);
+    Ok(result)
+}
+
+/// Complete a partially typed path, returning the completed text and the candidates.
+///
+/// With one candidate the path is completed fully (with a trailing `/` for directories), with
+/// several it is extended to their longest common prefix.
+pub fn complete_path(partial: &str) -> (String, Vec<String>) {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(i) => (&partial[..=i], &partial[i + 1..]),
+        None => ("", partial),
+    };
+    let search_dir = if dir.is_empty() { "." } else { dir };
+    let expanded = match search_dir.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()
+            .map(|home| home.join(rest))
+            .unwrap_or_else(|| Path::new(search_dir).to_path_buf()),
+        None => Path::new(search_dir).to_path_buf(),
+    };
+
+    let Ok(entries) = std::fs::read_dir(&expanded) else {
+        return (partial.to_string(), Vec::new());
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            Some(if is_dir { format!("{}/", name) } else { name })
+        })
+        .collect();
+    candidates.sort();
+
+    let completed = match candidates.as_slice() {
+        [] => prefix.to_string(),
+        [only] => only.clone(),
+        [first, rest @ ..] => rest.iter().fold(first.clone(), |common, candidate| {
+            common
+                .chars()
+                .zip(candidate.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect()
+        }),
+    };
+    (format!("{}{}", dir, completed), candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_typed_placeholders_with_defaults() {
+        let placeholders =
+            parse("ffmpeg -threads {{threads:int=4}} -i {{input:path}} {{input:path}}.mp3");
+        assert_eq!(
+            placeholders,
+            vec![
+                Placeholder {
+                    name: "threads".into(),
+                    kind: Kind::Int,
+                    default: Some("4".into()),
+                },
+                Placeholder {
+                    name: "input".into(),
+                    kind: Kind::Path,
+                    default: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_templates_that_are_not_placeholders() {
+        assert!(parse("docker ps --format '{{.Names}}'").is_empty());
+        assert!(parse("echo {{name:colour}}").is_empty());
+    }
+
+    #[test]
+    fn substitutes_quoted_values_once() {
+        let values = HashMap::from([("file".to_string(), "it's here.txt".to_string())]);
+        assert_eq!(
+            substitute("cat \"{{file:path}}\" {{file}}", &values, Shell::Bash).unwrap(),
+            "cat 'it'\\''s here.txt' 'it'\\''s here.txt'"
+        );
+    }
+
+    #[test]
+    fn escapes_values_inside_quoted_strings() {
+        let values = HashMap::from([("x".to_string(), "$(rm -rf ~) `id`".to_string())]);
+        assert_eq!(
+            substitute("echo \"pre {{x}} post\"", &values, Shell::Bash).unwrap(),
+            r#"echo "pre \$(rm -rf ~) \`id\` post""#
+        );
+        assert_eq!(
+            substitute("echo 'pre {{x}} post'", &values, Shell::Bash).unwrap(),
+            "echo 'pre ''$(rm -rf ~) `id`'' post'"
+        );
+        assert!(substitute("echo \"pre {{x}} post\"", &values, Shell::PowerShell).is_err());
+    }
+
+    #[test]
+    fn follows_each_dialects_escapes() {
+        let values = HashMap::from([("x".to_string(), "a b".to_string())]);
+        let fill = |command: &str, shell| substitute(command, &values, shell);
+
+        // In bash a backslash escapes the quote, leaving the placeholder inside the string.
+        assert_eq!(
+            fill(r#"echo "a\" {{x}}"#, Shell::Bash).unwrap(),
+            r#"echo "a\" a b"#
+        );
+        // PowerShell escapes with a backtick, so a backslash can end a path.
+        assert_eq!(
+            fill(r#"Get-Item "C:\" {{x}}"#, Shell::PowerShell).unwrap(),
+            r#"Get-Item "C:\" 'a b'"#
+        );
+        assert!(fill(r#"echo "C:`" {{x}}""#, Shell::PowerShell).is_err());
+        // Nushell only escapes inside double quotes.
+        assert_eq!(
+            fill(r#"echo a\ "{{x}}""#, Shell::Nushell).unwrap(),
+            r#"echo a\ r#'a b'#"#
+        );
+        assert!(fill(r#"echo "a\" {{x}}""#, Shell::Nushell).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quoted_strings_run_values_literally() {
+        let value = "$(echo oops) `echo oops` it's \\ \"quoted\"\nnext";
+        let values = HashMap::from([("x".to_string(), value.to_string())]);
+        for template in [
+            "printf %s \"@{{x}}@\"",
+            "printf %s '@{{x}}@'",
+            "printf %s @{{x}}@",
+            "printf %s \"'\"@\"{{x}}\"@",
+            "printf %s \"'\"@'{{x}}'@",
+        ] {
+            let command = substitute(template, &values, Shell::Bash).unwrap();
+            let output = Shell::Bash.command(&command).output().unwrap();
+            let expected = if template.contains("\"'\"") {
+                format!("'@{}@", value)
+            } else {
+                format!("@{}@", value)
+            };
+            assert_eq!(
+                String::from_utf8(output.stdout).unwrap(),
+                expected,
+                "{}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn validates_numbers() {
+        let threads = parse("{{threads:int}}").remove(0);
+        assert!(threads.validate("8").is_ok());
+        assert!(threads.validate("eight").is_err());
+        assert!(threads.validate("").is_err());
+    }
+}
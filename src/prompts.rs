@@ -0,0 +1,344 @@
+use clap::ValueEnum;
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use std::fmt;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// The variables templates can use, as `${name}`.
+pub const VARIABLES: &[&str] = &[
+    "shell",
+    "shell_notes",
+    "os",
+    "input",
+    "input_count",
+    "input_details",
+    "output",
+    "stdin",
+    "context",
+];
+
+/// The system prompt for generating commands.
+///
+/// Bump [`COMMAND_VERSION`] whenever this changes, so history and evaluations can tell the
+/// prompts apart.
+const COMMAND: &str = "\
+You are system designed to emit ${shell} commands, fulfilling the user's request. To achieve your goal, emit a single line command and only that command to achieve the user's request. When possible, use verbose command switches, to convey intent. You can safely assume whatever programs needed to achieve your goal are avaiable to you, such as jq ffmpeg, etc. When emitting your command, emit only the command, with no markdown formatting
+${shell_notes}
+The user is on ${os}.
+If the command needs values the user has not given, such as file names, use a placeholder of the form {{name:type}} or {{name:type=default}} in their place, where type is one of path, int, float or str. The user will be asked to fill these in before the command runs. Do not quote placeholders
+The user has given these inputs: ${input}
+There are ${input_count} input files, so handle every one of them, with a loop, a glob or xargs as suits the task, rather than just the first.
+This is what the input files hold, so the work can fit their actual contents: ${input_details}
+The user wants the results written to: ${output}
+The user is piping data into the command, so it must read standard input and fit on the end of their pipeline after a |, rather than naming an input file. Here is a sample of the data: ${stdin}
+The user has provided this context: ${context}
+";
+const COMMAND_VERSION: u32 = 5;
+
+/// The system prompt for generating scripts. Scripts are always bash, as they carry their own
+/// shebang rather than running in the user's shell.
+const SCRIPT: &str = "\
+You are system designed to write bash scripts, fulfilling the user's request. Emit a complete script, starting with the line #!/usr/bin/env bash followed by set -euo pipefail. Break the task into clear steps, with a short comment before each, and quote variables properly. When possible, use verbose command switches, to convey intent. You can safely assume whatever programs needed to achieve your goal are avaiable to you, such as jq ffmpeg, etc. Emit only the script, with no markdown formatting and no explanation outside of comments
+The user is on ${os}.
+The user has given these inputs: ${input}
+There are ${input_count} input files, so handle every one of them, with a loop, a glob or xargs as suits the task, rather than just the first.
+This is what the input files hold, so the work can fit their actual contents: ${input_details}
+The user wants the results written to: ${output}
+The user is piping data into the script, so it must read standard input rather than naming an input file. Here is a sample of the data: ${stdin}
+The user has provided this context: ${context}
+";
+const SCRIPT_VERSION: u32 = 5;
+
+/// The system prompt for safety checks. Its response must lead with Y or N, which is how the
+/// verdict is read.
+const SAFETY: &str = "\
+The user is going to pass in a ${shell} command. Your role is to inspect this for safety, evaluating whether or not the command could cause unexpected harm. Unexpected harm may be deleting or removing more files than intended. Your response should first lead with a Y for safe or N for unsafe. Your analysis should be concise, focussing on any caveats first and foremost
+";
+const SAFETY_VERSION: u32 = 1;
+
+/// The system prompts uhh sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PromptKind {
+    /// Generating a command.
+    Command,
+    /// Generating a script.
+    Script,
+    /// Checking a command for safety.
+    Safety,
+}
+
+impl PromptKind {
+    pub const ALL: [PromptKind; 3] = [PromptKind::Command, PromptKind::Script, PromptKind::Safety];
+
+    fn default_template(&self) -> (&'static str, u32) {
+        match self {
+            PromptKind::Command => (COMMAND, COMMAND_VERSION),
+            PromptKind::Script => (SCRIPT, SCRIPT_VERSION),
+            PromptKind::Safety => (SAFETY, SAFETY_VERSION),
+        }
+    }
+}
+
+impl fmt::Display for PromptKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PromptKind::Command => "command",
+            PromptKind::Script => "script",
+            PromptKind::Safety => "safety",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A system prompt, with `${name}` variables filled in when it is rendered.
+///
+/// A line using a variable with no value is left out, so optional details such as the input
+/// path only appear when they are given. `$${` is a literal `${`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub text: String,
+    /// `v1` and so on for the built in prompts, or `custom-` and a hash of the text for
+    /// overrides, so any edit gets a new version.
+    pub version: String,
+    /// The file the template was overridden by, if it was.
+    pub path: Option<PathBuf>,
+}
+
+impl Template {
+    pub fn default_for(kind: PromptKind) -> Self {
+        let (text, version) = kind.default_template();
+        Self {
+            text: text.to_string(),
+            version: format!("v{}", version),
+            path: None,
+        }
+    }
+
+    /// A template read from a file, failing if it uses an unknown variable.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        Self::check(&text).wrap_err_with(|| format!("Invalid prompt in {}", path.display()))?;
+        let mut hasher = fnv::FnvHasher::default();
+        hasher.write(text.as_bytes());
+        Ok(Self {
+            text,
+            version: format!("custom-{:08x}", hasher.finish() as u32),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Check that a template only uses known variables.
+    pub fn check(text: &str) -> Result<()> {
+        for line in text.lines() {
+            for name in variables(line) {
+                if !VARIABLES.contains(&name) {
+                    return Err(eyre!(
+                        "Unknown variable ${{{}}}, expected one of {}. Write $${{ for a literal ${{",
+                        name,
+                        VARIABLES.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in the variables, leaving out lines that use a variable with no value.
+    pub fn render(&self, vars: &Vars) -> String {
+        let mut rendered = String::new();
+        'lines: for line in self.text.lines() {
+            let mut out = String::new();
+            let mut rest = line;
+            while let Some(start) = rest.find("${") {
+                if rest[..start].ends_with('$') {
+                    out.push_str(&rest[..start - 1]);
+                    out.push_str("${");
+                    rest = &rest[start + 2..];
+                    continue;
+                }
+                let Some(end) = rest[start..].find('}') else {
+                    break;
+                };
+                let name = &rest[start + 2..start + end];
+                match vars.get(name) {
+                    Some(value) => {
+                        out.push_str(&rest[..start]);
+                        out.push_str(value);
+                    }
+                    None => continue 'lines,
+                }
+                rest = &rest[start + end + 1..];
+            }
+            out.push_str(rest);
+            rendered.push_str(&out);
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
+
+/// The names of the variables a line uses, skipping escaped `$${`.
+fn variables(line: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("${") {
+        let escaped = rest[..start].ends_with('$');
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        if !escaped {
+            names.push(&rest[start + 2..start + end]);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    names
+}
+
+/// The values of the template variables for a request.
+#[derive(Debug, Clone, Default)]
+pub struct Vars {
+    pub shell: String,
+    pub shell_notes: String,
+    pub os: String,
+    pub input: Option<String>,
+    /// How many input files there are, when there is more than one.
+    pub input_count: Option<String>,
+    /// A summary of what the input files hold, from [`crate::sniff`].
+    pub input_details: Option<String>,
+    /// The output paths, listed a line each when there are several.
+    pub output: Option<String>,
+    /// A sample of the data piped into uhh, from [`crate::pipe`].
+    pub stdin: Option<String>,
+    pub context: Option<String>,
+}
+
+impl Vars {
+    /// The value of a variable, or `None` if it is unset or empty.
+    fn get(&self, name: &str) -> Option<&str> {
+        let value = match name {
+            "shell" => Some(&self.shell),
+            "shell_notes" => Some(&self.shell_notes),
+            "os" => Some(&self.os),
+            "input" => self.input.as_ref(),
+            "input_count" => self.input_count.as_ref(),
+            "input_details" => self.input_details.as_ref(),
+            "output" => self.output.as_ref(),
+            "stdin" => self.stdin.as_ref(),
+            "context" => self.context.as_ref(),
+            _ => None,
+        }?;
+        Some(value.as_str()).filter(|value| !value.is_empty())
+    }
+}
+
+/// The templates for every prompt, with any overrides from the config directory.
+#[derive(Debug, Clone)]
+pub struct Templates {
+    pub command: Template,
+    pub script: Template,
+    pub safety: Template,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self {
+            command: Template::default_for(PromptKind::Command),
+            script: Template::default_for(PromptKind::Script),
+            safety: Template::default_for(PromptKind::Safety),
+        }
+    }
+}
+
+impl Templates {
+    /// Where overrides live, e.g. `~/.config/uhh/prompts` on Linux.
+    pub fn dir() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("prompts"))
+    }
+
+    /// The file overriding a prompt, e.g. `~/.config/uhh/prompts/command.txt`.
+    pub fn path(kind: PromptKind) -> Option<PathBuf> {
+        Self::dir().map(|dir| dir.join(format!("{}.txt", kind)))
+    }
+
+    /// Load the templates, using any overrides in the config directory.
+    pub fn load() -> Result<Self> {
+        match Self::dir() {
+            Some(dir) => Self::load_from(&dir),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(dir: &Path) -> Result<Self> {
+        let mut templates = Self::default();
+        for kind in PromptKind::ALL {
+            let path = dir.join(format!("{}.txt", kind));
+            if path.exists() {
+                *templates.get_mut(kind) = Template::from_file(&path)?;
+            }
+        }
+        Ok(templates)
+    }
+
+    pub fn get(&self, kind: PromptKind) -> &Template {
+        match kind {
+            PromptKind::Command => &self.command,
+            PromptKind::Script => &self.script,
+            PromptKind::Safety => &self.safety,
+        }
+    }
+
+    fn get_mut(&mut self, kind: PromptKind) -> &mut Template {
+        match kind {
+            PromptKind::Command => &mut self.command,
+            PromptKind::Script => &mut self.script,
+            PromptKind::Safety => &mut self.safety,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_variables_and_drops_lines_without_values() {
+        let template = Template {
+            text: "Emit ${shell} commands.\n${shell_notes}\nInput: ${input}\nUse $${HOME}, {{name:path}}\n".into(),
+            version: "v1".into(),
+            path: None,
+        };
+        let vars = Vars {
+            shell: "fish".into(),
+            input: Some("in.txt".into()),
+            ..Vars::default()
+        };
+        assert_eq!(
+            template.render(&vars),
+            "Emit fish commands.\nInput: in.txt\nUse ${HOME}, {{name:path}}\n"
+        );
+        assert_eq!(variables("a ${x} $${y} ${z}"), ["x", "z"]);
+    }
+
+    #[test]
+    fn overrides_get_versions_from_their_text() {
+        let dir = std::env::temp_dir().join(format!("uhh-prompts-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("safety.txt"), "Check this ${shell} command.\n").unwrap();
+        let templates = Templates::load_from(&dir).unwrap();
+        assert_eq!(
+            templates.command,
+            Template::default_for(PromptKind::Command)
+        );
+        assert!(templates.safety.version.starts_with("custom-"));
+
+        std::fs::write(dir.join("safety.txt"), "Check this ${sh} command.\n").unwrap();
+        assert!(Templates::load_from(&dir).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+
+/// Words too common to say anything about what a prompt is asking for.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "how", "i", "in", "into", "is",
+    "it", "me", "my", "of", "on", "or", "the", "this", "to", "with",
+];
+
+/// BM25 tuning parameters, at their usual values.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// Split text into lowercase search terms, dropping punctuation and stopwords.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// A BM25 index over a small set of documents, for ranking them against a query.
+#[derive(Debug)]
+pub struct Bm25 {
+    docs: Vec<Vec<String>>,
+    doc_freq: HashMap<String, usize>,
+    avg_len: f64,
+}
+
+impl Bm25 {
+    pub fn new<'a>(docs: impl IntoIterator<Item = &'a str>) -> Self {
+        let docs: Vec<Vec<String>> = docs.into_iter().map(tokenize).collect();
+        let mut doc_freq = HashMap::new();
+        for doc in &docs {
+            for term in doc.iter().collect::<HashSet<_>>() {
+                *doc_freq.entry(term.clone()).or_insert(0) += 1;
+            }
+        }
+        let total: usize = docs.iter().map(Vec::len).sum();
+        let avg_len = total as f64 / docs.len().max(1) as f64;
+        Self {
+            docs,
+            doc_freq,
+            avg_len,
+        }
+    }
+
+    /// The BM25 score of each document against the query.
+    pub fn scores(&self, query: &str) -> Vec<f64> {
+        let terms: HashSet<String> = tokenize(query).into_iter().collect();
+        let n = self.docs.len() as f64;
+        self.docs
+            .iter()
+            .map(|doc| {
+                let len = doc.len() as f64;
+                terms
+                    .iter()
+                    .map(|term| {
+                        let tf = doc.iter().filter(|t| *t == term).count() as f64;
+                        if tf == 0.0 {
+                            return 0.0;
+                        }
+                        let df = self.doc_freq.get(term).copied().unwrap_or(0) as f64;
+                        let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+                        idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * len / self.avg_len))
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// The indices of the `limit` best documents, best first.
+    ///
+    /// Only documents sharing at least `min_overlap` (0 to 1) of the query's terms are included,
+    /// so a single common word isn't enough to count as similar.
+    pub fn top(&self, query: &str, limit: usize, min_overlap: f64) -> Vec<usize> {
+        let terms: HashSet<String> = tokenize(query).into_iter().collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let scores = self.scores(query);
+        let mut ranked: Vec<usize> = (0..self.docs.len())
+            .filter(|&i| {
+                let shared = terms.iter().filter(|t| self.docs[i].contains(t)).count();
+                scores[i] > 0.0 && shared as f64 / terms.len() as f64 >= min_overlap
+            })
+            .collect();
+        // A stable sort keeps earlier documents first on ties.
+        ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        ranked.truncate(limit);
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_documents_sharing_rare_terms_first() {
+        let index = Bm25::new([
+            "list files in a directory",
+            "compress a directory into a tarball",
+            "find large files",
+        ]);
+        assert_eq!(index.top("compress this directory", 5, 0.5), vec![1, 0]);
+        assert_eq!(index.top("find big files", 5, 0.3), vec![2, 0]);
+        assert!(index.top("the", 5, 0.0).is_empty());
+    }
+
+    #[test]
+    fn orders_by_score_then_position() {
+        let index = Bm25::new([
+            "resize images",
+            "resize images",
+            "resize all the images in a folder of holiday photos",
+            "images images resize",
+        ]);
+        // Repeating a term counts for more, a longer document for less, and ties keep their
+        // order.
+        assert_eq!(index.top("resize images", 5, 0.5), vec![3, 0, 1, 2]);
+        assert_eq!(index.top("resize images", 2, 0.5), vec![3, 0]);
+        assert_eq!(index.top("resize holiday videos", 5, 0.5), vec![2]);
+        assert!(Bm25::new([]).top("resize", 5, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+use color_eyre::Result;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    process::Stdio,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// Check a generated script without running it.
+///
+/// Runs `bash -n` to catch syntax errors, and `shellcheck` when it is installed, returning a
+/// short report for the review pane.
+pub async fn check(script: &str) -> String {
+    let mut report = Vec::new();
+
+    match run_with_stdin("bash", &["-n"], script).await {
+        Ok((true, _)) => report.push("bash -n: no syntax errors".to_string()),
+        Ok((false, output)) => report.push(format!("bash -n found problems:\n{}", output.trim())),
+        Err(e) => report.push(format!("bash -n could not run: {}", e)),
+    }
+
+    // shellcheck is optional, so only mention it when it could run.
+    match run_with_stdin("shellcheck", &["--format=gcc", "-"], script).await {
+        Ok((true, _)) => report.push("shellcheck: no issues".to_string()),
+        Ok((false, output)) => {
+            report.push(format!("shellcheck found issues:\n{}", output.trim()));
+        }
+        Err(_) => {}
+    }
+
+    report.join("\n")
+}
+
+/// Save a script to `path`, marking it executable.
+///
+/// An existing file is only replaced when `overwrite` is set, otherwise saving fails with an
+/// [`io::ErrorKind::AlreadyExists`] error, so the user can be asked first.
+pub fn save(path: &Path, script: &str, overwrite: bool) -> io::Result<()> {
+    let mut contents = script.to_string();
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    options.open(path)?.write_all(contents.as_bytes())?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        permissions.set_mode(permissions.mode() | 0o111);
+        std::fs::set_permissions(path, permissions)?;
+    }
+
+    Ok(())
+}
+
+/// Run a program with `input` on stdin, returning whether it succeeded and its combined output.
+async fn run_with_stdin(program: &str, args: &[&str], input: &str) -> Result<(bool, String)> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok((output.status.success(), text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_executable_scripts_without_overwriting() {
+        let path = std::env::temp_dir().join(format!("uhh-script-test-{}.sh", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        save(&path, "echo one", false).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "echo one\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o111, 0o111);
+        }
+
+        let error = save(&path, "echo two", false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "echo one\n");
+
+        save(&path, "echo two\n", true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "echo two\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn reports_syntax_errors() {
+        let report = check("#!/bin/bash\necho hello\n").await;
+        assert!(
+            report.starts_with("bash -n: no syntax errors"),
+            "{}",
+            report
+        );
+
+        let report = check("#!/bin/bash\nif true; then\necho hello\n").await;
+        assert!(
+            report.starts_with("bash -n found problems:\n"),
+            "{}",
+            report
+        );
+        assert!(report.contains("syntax error"), "{}", report);
+    }
+}
@@ -0,0 +1,242 @@
+use clap::ValueEnum;
+use std::{ffi::OsStr, fmt, path::Path, process::Command};
+
+/// A shell dialect to generate and run commands in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Sh,
+    Bash,
+    Zsh,
+    Fish,
+    #[value(name = "nu")]
+    Nushell,
+    #[value(name = "pwsh")]
+    PowerShell,
+}
+
+impl Shell {
+    /// Detect the user's shell from `$SHELL`, falling back to the platform default.
+    pub fn detect() -> Self {
+        Self::from_env(std::env::var_os("SHELL").as_deref())
+    }
+
+    /// The shell named by a `$SHELL` value, falling back to PowerShell on Windows and bash
+    /// elsewhere.
+    fn from_env(shell: Option<&OsStr>) -> Self {
+        shell
+            .and_then(|shell| {
+                let name = Path::new(shell).file_stem()?.to_str()?.to_string();
+                Self::from_program(&name)
+            })
+            .unwrap_or(if cfg!(windows) {
+                Shell::PowerShell
+            } else {
+                Shell::Bash
+            })
+    }
+
+    /// Match a shell by its program name, e.g. `fish` or `pwsh`.
+    pub fn from_program(name: &str) -> Option<Self> {
+        match name {
+            "sh" | "dash" | "ash" => Some(Shell::Sh),
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "nu" => Some(Shell::Nushell),
+            "pwsh" | "powershell" => Some(Shell::PowerShell),
+            _ => None,
+        }
+    }
+
+    /// Dialect-specific guidance for the model, beyond naming the shell.
+    pub fn prompt_notes(&self) -> &'static str {
+        match self {
+            Shell::Sh => {
+                "Stick to POSIX sh syntax, avoiding bashisms such as [[ ]], arrays and <(...)."
+            }
+            Shell::Bash | Shell::Zsh => "",
+            Shell::Fish => {
+                "Use fish syntax: command substitution is (...) not $(...), variables are set with `set` (`set -x` to export) rather than VAR=value or export, and loops end with `end`."
+            }
+            Shell::Nushell => {
+                "Use nushell syntax and its structured pipelines and built-in commands (e.g. ls, open, where, each, get) where they fit. Command substitution and POSIX redirection do not exist; use ( ) subexpressions, `save` and `o>`/`e>` instead."
+            }
+            Shell::PowerShell => {
+                "Use PowerShell cmdlets and syntax, e.g. Get-ChildItem, Where-Object and $env:VAR, rather than POSIX utilities and syntax."
+            }
+        }
+    }
+
+    /// Quote a value so the shell passes it to a command as a single, literal argument.
+    pub fn quote(&self, value: &str) -> String {
+        // These characters are only literal in POSIX-like shells. PowerShell makes arrays of
+        // commas and splats @ variables, and nushell parses bare words in its own ways, so they
+        // always get quotes. Zsh expands a leading = to the path of a command.
+        let plain = !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-./,:=+@%".contains(c))
+            && match self {
+                Shell::Sh | Shell::Bash | Shell::Fish => true,
+                Shell::Zsh => !value.starts_with('='),
+                Shell::Nushell | Shell::PowerShell => false,
+            };
+        if plain {
+            return value.to_string();
+        }
+        match self {
+            Shell::Sh | Shell::Bash | Shell::Zsh => format!("'{}'", value.replace('\'', r"'\''")),
+            Shell::Fish => format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'")),
+            // Raw strings can hold anything but their own delimiter, so pick enough hashes.
+            Shell::Nushell => {
+                let mut hashes = "#".to_string();
+                while value.contains(&format!("'{}", hashes)) {
+                    hashes.push('#');
+                }
+                format!("r{0}'{1}'{0}", hashes, value)
+            }
+            // PowerShell takes the typographic single quotes as delimiters too, so every one is
+            // doubled.
+            Shell::PowerShell => {
+                let mut quoted = String::from("'");
+                for c in value.chars() {
+                    if POWERSHELL_SINGLE_QUOTES.contains(&c) {
+                        quoted.push(c);
+                    }
+                    quoted.push(c);
+                }
+                quoted.push('\'');
+                quoted
+            }
+        }
+    }
+
+    /// A command which runs `command` in this shell.
+    pub fn command(&self, command: &str) -> Command {
+        let (program, flag) = match self {
+            Shell::Sh => ("sh", "-c"),
+            Shell::Bash => ("bash", "-c"),
+            Shell::Zsh => ("zsh", "-c"),
+            Shell::Fish => ("fish", "-c"),
+            Shell::Nushell => ("nu", "-c"),
+            // Windows PowerShell ships with Windows, PowerShell 7 has to be installed.
+            Shell::PowerShell if cfg!(windows) => ("powershell", "-Command"),
+            Shell::PowerShell => ("pwsh", "-Command"),
+        };
+        let mut cmd = Command::new(program);
+        cmd.arg(flag).arg(command);
+        cmd
+    }
+}
+
+/// The characters PowerShell treats as single quotes.
+const POWERSHELL_SINGLE_QUOTES: [char; 5] = ['\'', '\u{2018}', '\u{2019}', '\u{201a}', '\u{201b}'];
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Shell::Sh => "POSIX sh",
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Nushell => "nushell",
+            Shell::PowerShell => "PowerShell",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AWKWARD: &str = "it's $HOME\nand `date` \\ \"done\"";
+    /// A value that ends PowerShell's single-quoted strings with typographic quotes.
+    const BREAKOUT: &str = "x\u{2019}; Remove-Item -Recurse ~; \u{2018}";
+
+    #[test]
+    fn detects_the_shell_from_its_program() {
+        assert_eq!(Shell::from_program("dash"), Some(Shell::Sh));
+        assert_eq!(Shell::from_program("bash"), Some(Shell::Bash));
+        assert_eq!(Shell::from_program("powershell"), Some(Shell::PowerShell));
+        assert_eq!(Shell::from_program("nu"), Some(Shell::Nushell));
+        assert_eq!(Shell::from_program("tcsh"), None);
+
+        let detect = |shell: &str| Shell::from_env(Some(OsStr::new(shell)));
+        assert_eq!(detect("/usr/bin/fish"), Shell::Fish);
+        assert_eq!(detect("/bin/zsh"), Shell::Zsh);
+        let fallback = if cfg!(windows) {
+            Shell::PowerShell
+        } else {
+            Shell::Bash
+        };
+        assert_eq!(detect("/bin/tcsh"), fallback);
+        assert_eq!(Shell::from_env(None), fallback);
+    }
+
+    #[test]
+    fn quotes_for_each_dialect() {
+        assert_eq!(Shell::Bash.quote("notes.txt"), "notes.txt");
+        assert_eq!(Shell::Bash.quote(""), "''");
+        assert_eq!(
+            Shell::Sh.quote(AWKWARD),
+            "'it'\\''s $HOME\nand `date` \\ \"done\"'"
+        );
+        assert_eq!(
+            Shell::Fish.quote(AWKWARD),
+            "'it\\'s $HOME\nand `date` \\\\ \"done\"'"
+        );
+        assert_eq!(
+            Shell::Nushell.quote(AWKWARD),
+            "r#'it's $HOME\nand `date` \\ \"done\"'#"
+        );
+        assert_eq!(Shell::Nushell.quote("a'#b"), "r##'a'#b'##");
+        assert_eq!(
+            Shell::PowerShell.quote(AWKWARD),
+            "'it''s $HOME\nand `date` \\ \"done\"'"
+        );
+        assert_eq!(
+            Shell::PowerShell.quote(BREAKOUT),
+            "'x\u{2019}\u{2019}; Remove-Item -Recurse ~; \u{2018}\u{2018}'"
+        );
+    }
+
+    #[test]
+    fn quotes_what_only_posix_shells_take_literally() {
+        assert_eq!(Shell::Sh.quote("a,b@c"), "a,b@c");
+        assert_eq!(Shell::PowerShell.quote("a,b"), "'a,b'");
+        assert_eq!(Shell::PowerShell.quote("@x"), "'@x'");
+        assert_eq!(Shell::PowerShell.quote("notes.txt"), "'notes.txt'");
+        assert_eq!(Shell::Nushell.quote("notes.txt"), "r#'notes.txt'#");
+        assert_eq!(Shell::Zsh.quote("=ls"), "'=ls'");
+        assert_eq!(Shell::Zsh.quote("a=b"), "a=b");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quoted_values_reach_the_command_unchanged() {
+        for shell in [Shell::Sh, Shell::Bash] {
+            let output = shell
+                .command(&format!("printf %s {}", shell.quote(AWKWARD)))
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), AWKWARD);
+        }
+    }
+
+    /// Runs only where PowerShell is installed.
+    #[test]
+    fn typographic_quotes_reach_powershell_unchanged() {
+        let shell = Shell::PowerShell;
+        let Ok(output) = shell
+            .command(&format!("Write-Output {}", shell.quote(BREAKOUT)))
+            .output()
+        else {
+            return;
+        };
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap().trim_end(),
+            BREAKOUT
+        );
+    }
+}
@@ -0,0 +1,457 @@
+use color_eyre::{Result, eyre::WrapErr};
+use serde_json::Value;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// How much of a file is read to identify it.
+const HEAD_BYTES: usize = 8192;
+/// JSON files up to this size are parsed in full to find their shape, larger ones are previewed.
+const MAX_PARSE_BYTES: u64 = 8 * 1024 * 1024;
+/// How many lines of a text file, or records of a CSV file after its header, are previewed.
+const PREVIEW_LINES: usize = 10;
+const CSV_RECORDS: usize = 3;
+/// Longer preview lines are cut short, so one huge line can't flood the prompt.
+const MAX_LINE_CHARS: usize = 200;
+/// How much of a file is read for a preview, so a huge file with few or no line breaks, such as
+/// minified JSON, isn't read in full.
+const PREVIEW_BYTES: u64 = 64 * 1024;
+/// How many entries of an archive or directory are listed.
+const MAX_ENTRIES: usize = 20;
+/// How deep, and how many keys per object, a JSON shape goes.
+const MAX_DEPTH: usize = 4;
+const MAX_KEYS: usize = 20;
+
+/// What an input file holds, as far as can be told without understanding it: its type and size,
+/// and for common formats a look inside, such as a CSV header or the shape of some JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub mime: &'static str,
+    /// The size in bytes, for files rather than directories.
+    pub size: Option<u64>,
+    pub details: Vec<String>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mime)?;
+        if let Some(size) = self.size {
+            write!(f, ", {}", human_size(size))?;
+        }
+        for line in &self.details {
+            write!(f, "\n{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Summarise the file or directory at a path.
+///
+/// Media files are probed with `ffprobe`, and archives listed with `unzip` or `tar`, when they
+/// are installed. Without them the summary just has the type and size.
+pub fn sniff(path: &Path) -> Result<Summary> {
+    let metadata =
+        std::fs::metadata(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    if metadata.is_dir() {
+        return sniff_dir(path);
+    }
+
+    let mut head = Vec::with_capacity(HEAD_BYTES);
+    File::open(path)
+        .and_then(|file| file.take(HEAD_BYTES as u64).read_to_end(&mut head))
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let mime = mime_type(&head, &extension);
+
+    let details = match mime {
+        "application/json" if metadata.len() <= MAX_PARSE_BYTES => {
+            json_details(path).unwrap_or_else(|| preview(path, PREVIEW_LINES, "First lines:"))
+        }
+        "application/x-ndjson" => ndjson_details(path),
+        "text/csv" | "text/tab-separated-values" => {
+            preview(path, CSV_RECORDS + 1, "Header and first records:")
+        }
+        "application/zip" => list_archive(path, &[("unzip", &["-Z1"]), ("tar", &["-tf"])]),
+        "application/gzip"
+        | "application/x-bzip2"
+        | "application/x-xz"
+        | "application/zstd"
+        | "application/x-tar"
+        | "application/x-7z-compressed" => list_archive(path, &[("tar", &["-tf"])]),
+        mime if mime.starts_with("text/")
+            || matches!(
+                mime,
+                "application/json" | "application/yaml" | "application/toml" | "application/xml"
+            ) =>
+        {
+            preview(path, PREVIEW_LINES, "First lines:")
+        }
+        mime if ["image/", "audio/", "video/"]
+            .iter()
+            .any(|prefix| mime.starts_with(prefix)) =>
+        {
+            probe_media(path)
+        }
+        _ => Vec::new(),
+    };
+
+    Ok(Summary {
+        mime,
+        size: Some(metadata.len()),
+        details,
+    })
+}
+
+fn sniff_dir(path: &Path) -> Result<Summary> {
+    let mut names: Vec<String> = std::fs::read_dir(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => name + "/",
+                _ => name,
+            }
+        })
+        .collect();
+    names.sort();
+    Ok(Summary {
+        mime: "inode/directory",
+        size: None,
+        details: listing(names),
+    })
+}
+
+/// Identify a file from its first bytes, falling back on its extension for text formats.
+fn mime_type(head: &[u8], extension: &str) -> &'static str {
+    let starts = |magic: &[u8]| head.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if starts(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        "image/gif"
+    } else if starts(b"RIFF") && at(8, b"WEBP") {
+        "image/webp"
+    } else if starts(b"RIFF") && at(8, b"WAVE") {
+        "audio/wav"
+    } else if starts(b"RIFF") && at(8, b"AVI ") {
+        "video/x-msvideo"
+    } else if at(4, b"ftyp") {
+        match head.get(8..12) {
+            Some(b"qt  ") => "video/quicktime",
+            Some(b"M4A ") => "audio/mp4",
+            Some(b"heic") | Some(b"mif1") => "image/heic",
+            _ => "video/mp4",
+        }
+    } else if starts(b"\x1a\x45\xdf\xa3") {
+        if extension == "webm" {
+            "video/webm"
+        } else {
+            "video/x-matroska"
+        }
+    } else if starts(b"ID3") || starts(b"\xff\xfb") || starts(b"\xff\xf3") {
+        "audio/mpeg"
+    } else if starts(b"OggS") {
+        "audio/ogg"
+    } else if starts(b"fLaC") {
+        "audio/flac"
+    } else if starts(b"%PDF-") {
+        "application/pdf"
+    } else if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") {
+        "application/zip"
+    } else if starts(b"\x1f\x8b") {
+        "application/gzip"
+    } else if starts(b"BZh") {
+        "application/x-bzip2"
+    } else if starts(b"\xfd7zXZ\x00") {
+        "application/x-xz"
+    } else if starts(b"\x28\xb5\x2f\xfd") {
+        "application/zstd"
+    } else if starts(b"7z\xbc\xaf\x27\x1c") {
+        "application/x-7z-compressed"
+    } else if at(257, b"ustar") {
+        "application/x-tar"
+    } else if starts(b"SQLite format 3\x00") {
+        "application/vnd.sqlite3"
+    } else if starts(b"\x7fELF") {
+        "application/x-executable"
+    } else if is_text(head) {
+        text_type(head, extension)
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Whether the bytes look like UTF-8 text, allowing for a character cut off at the end.
+fn is_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+fn text_type(head: &[u8], extension: &str) -> &'static str {
+    match extension {
+        "json" => return "application/json",
+        "jsonl" | "ndjson" => return "application/x-ndjson",
+        "csv" => return "text/csv",
+        "tsv" => return "text/tab-separated-values",
+        "yaml" | "yml" => return "application/yaml",
+        "toml" => return "application/toml",
+        "xml" => return "application/xml",
+        "html" | "htm" => return "text/html",
+        "md" | "markdown" => return "text/markdown",
+        _ => {}
+    }
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start();
+    if text.starts_with('{') || text.starts_with('[') {
+        "application/json"
+    } else if text.starts_with("#!") {
+        "text/x-script"
+    } else if text.starts_with("<?xml") {
+        "application/xml"
+    } else {
+        "text/plain"
+    }
+}
+
+/// The shape of a JSON file, and how many items it has if it is an array.
+fn json_details(path: &Path) -> Option<Vec<String>> {
+    let file = File::open(path).ok()?;
+    let value: Value = serde_json::from_reader(BufReader::new(file)).ok()?;
+    let mut details = vec![format!("Shape: {}", shape(&value, 0))];
+    if let Value::Array(items) = &value {
+        details.push(format!("Items: {}", items.len()));
+    }
+    Some(details)
+}
+
+/// The shape of the first record of a JSON lines file.
+fn ndjson_details(path: &Path) -> Vec<String> {
+    let first = lines(path, 1).into_iter().next();
+    match first.and_then(|line| serde_json::from_str::<Value>(&line).ok()) {
+        Some(record) => vec![format!("First record shape: {}", shape(&record, 0))],
+        None => preview(path, PREVIEW_LINES, "First lines:"),
+    }
+}
+
+/// Describe a JSON value by its keys and the types of its values, e.g.
+/// `{"name": string, "tags": [string]}`. Arrays are described by their first item.
+fn shape(value: &Value, depth: usize) -> String {
+    match value {
+        Value::Null => "null".into(),
+        Value::Bool(_) => "boolean".into(),
+        Value::Number(_) => "number".into(),
+        Value::String(_) => "string".into(),
+        Value::Array(items) => match items.first() {
+            None => "[]".into(),
+            Some(_) if depth >= MAX_DEPTH => "[...]".into(),
+            Some(first) => format!("[{}]", shape(first, depth + 1)),
+        },
+        Value::Object(map) if map.is_empty() => "{}".into(),
+        Value::Object(_) if depth >= MAX_DEPTH => "{...}".into(),
+        Value::Object(map) => {
+            let mut fields: Vec<String> = map
+                .iter()
+                .take(MAX_KEYS)
+                .map(|(key, value)| format!("{:?}: {}", key, shape(value, depth + 1)))
+                .collect();
+            if map.len() > MAX_KEYS {
+                fields.push("...".into());
+            }
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+fn preview(path: &Path, count: usize, heading: &str) -> Vec<String> {
+    let lines = lines(path, count);
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    std::iter::once(heading.to_string()).chain(lines).collect()
+}
+
+/// The first lines of a file, each cut to a sensible length.
+fn lines(path: &Path, count: usize) -> Vec<String> {
+    let mut head = Vec::new();
+    let read = File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut head));
+    if read.is_err() {
+        return Vec::new();
+    }
+    // The last line runs on past what was read, unless the whole file was.
+    let unfinished = head.len() as u64 == PREVIEW_BYTES;
+    let mut lines: Vec<&[u8]> = head.split(|&byte| byte == b'\n').collect();
+    if lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let last = lines.len().saturating_sub(1);
+    lines
+        .into_iter()
+        .take(count)
+        .enumerate()
+        .map(|(i, line)| {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_end_matches('\r');
+            if line.chars().count() > MAX_LINE_CHARS {
+                line.chars().take(MAX_LINE_CHARS).collect::<String>() + "..."
+            } else if unfinished && i == last {
+                line.to_string() + "..."
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
+
+/// The container and streams of a media file, from `ffprobe`.
+fn probe_media(path: &Path) -> Vec<String> {
+    let args = [
+        "-v",
+        "error",
+        "-show_entries",
+        "format=format_name,duration,bit_rate:stream=codec_type,codec_name,width,height,sample_rate,channels",
+        "-of",
+        "compact",
+    ];
+    run("ffprobe", &args, path)
+        .map(|output| {
+            output
+                .lines()
+                .filter(|line| !line.is_empty())
+                .take(PREVIEW_LINES)
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The files in an archive, from the first of the listing programs that works.
+fn list_archive(path: &Path, programs: &[(&str, &[&str])]) -> Vec<String> {
+    programs
+        .iter()
+        .find_map(|(program, args)| run(program, args, path))
+        .map(|output| listing(output.lines().map(String::from).collect()))
+        .unwrap_or_default()
+}
+
+fn listing(entries: Vec<String>) -> Vec<String> {
+    let mut details = vec![format!("Entries: {}", entries.len())];
+    details.extend(entries.iter().take(MAX_ENTRIES).cloned());
+    if entries.len() > MAX_ENTRIES {
+        details.push(format!("...and {} more", entries.len() - MAX_ENTRIES));
+    }
+    details
+}
+
+/// The output of a program run on a file, if it is installed and succeeds.
+fn run(program: &str, args: &[&str], path: &Path) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifies_files_by_magic_then_extension() {
+        assert_eq!(mime_type(b"\x89PNG\r\n\x1a\n....", "txt"), "image/png");
+        assert_eq!(mime_type(b"\x1f\x8b\x08\x00", "json"), "application/gzip");
+        assert_eq!(mime_type(b"\x00\x00\x00\x18ftypisom", ""), "video/mp4");
+        assert_eq!(mime_type(b"a,b\n1,2\n", "csv"), "text/csv");
+        assert_eq!(mime_type(b"  [{\"a\": 1}]", ""), "application/json");
+        assert_eq!(mime_type(b"caf\xc3", ""), "text/plain");
+        assert_eq!(mime_type(b"\x00\x01\x02", ""), "application/octet-stream");
+    }
+
+    #[test]
+    fn summarises_json_and_csv() {
+        let dir = std::env::temp_dir().join(format!("uhh-sniff-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let json = dir.join("repos.json");
+        std::fs::write(
+            &json,
+            r#"[{"name": "uhh", "stars": 12, "topics": ["cli"], "owner": {"login": "x"}}, {}]"#,
+        )
+        .unwrap();
+        let summary = sniff(&json).unwrap();
+        assert_eq!(
+            summary.to_string(),
+            "application/json, 78 bytes\n\
+             Shape: [{\"name\": string, \"owner\": {\"login\": string}, \"stars\": number, \"topics\": [string]}]\n\
+             Items: 2"
+        );
+
+        let csv = dir.join("sales.csv");
+        std::fs::write(&csv, "date,region,total\r\n2024-01-01,eu,10\r\n2024-01-02,us,12\r\n2024-01-03,eu,9\r\n2024-01-04,us,3\r\n").unwrap();
+        assert_eq!(
+            sniff(&csv).unwrap().details,
+            [
+                "Header and first records:",
+                "date,region,total",
+                "2024-01-01,eu,10",
+                "2024-01-02,us,12",
+                "2024-01-03,eu,9"
+            ]
+        );
+
+        let summary = sniff(&dir).unwrap();
+        assert_eq!(summary.details, ["Entries: 2", "repos.json", "sales.csv"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn previews_the_start_of_huge_lines() {
+        let path = std::env::temp_dir().join(format!("uhh-sniff-line-{}.log", std::process::id()));
+        std::fs::write(&path, "a".repeat(4 * PREVIEW_BYTES as usize)).unwrap();
+        assert_eq!(
+            lines(&path, PREVIEW_LINES),
+            [format!("{}...", "a".repeat(MAX_LINE_CHARS))]
+        );
+
+        std::fs::write(&path, "short\n".repeat(PREVIEW_BYTES as usize)).unwrap();
+        assert_eq!(lines(&path, 2), ["short", "short"]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
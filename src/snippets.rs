@@ -0,0 +1,549 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::rank::Bm25;
+
+/// A saved prompt and the command it produced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub prompt: String,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub explanation: String,
+    /// The name of the pack the snippet came from, or `None` for the user's own snippets.
+    #[serde(skip)]
+    pub pack: Option<String>,
+}
+
+impl Snippet {
+    /// Whether the snippet matches every term of a search query.
+    ///
+    /// Terms starting with `#` must match a tag exactly, other terms can appear anywhere in the
+    /// name, tags, prompt or command. Matching is case insensitive.
+    pub fn matches(&self, query: &str) -> bool {
+        let haystack = format!(
+            "{}\n{}\n{}\n{}",
+            self.name,
+            self.tags.join(" "),
+            self.prompt,
+            self.command
+        )
+        .to_lowercase();
+        query.split_whitespace().all(|term| {
+            let term = term.to_lowercase();
+            match term.strip_prefix('#') {
+                Some(tag) => self.tags.iter().any(|t| t.to_lowercase() == tag),
+                None => haystack.contains(&term),
+            }
+        })
+    }
+
+    /// The text the snippet is matched against when ranking it for a prompt.
+    fn search_text(&self) -> String {
+        format!("{} {} {}", self.name, self.tags.join(" "), self.prompt)
+    }
+}
+
+/// The version of the pack file format written by this version of uhh.
+pub const PACK_FORMAT: u32 = 1;
+
+/// A shareable collection of snippets, stored as a TOML or JSON file.
+///
+/// Packs are installed into [`Library::packs_dir`], either with `uhh pack import` or by cloning a
+/// repository containing pack files there, and their snippets are offered before the user's own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnippetPack {
+    /// The version of the file format, see [`PACK_FORMAT`].
+    pub format: u32,
+    pub name: String,
+    /// The version of the pack's contents, e.g. `1.2.0`, used to avoid downgrades on import.
+    #[serde(default)]
+    pub version: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(default, rename = "snippet", alias = "snippets")]
+    pub snippets: Vec<Snippet>,
+    /// The file the pack was read from, if it was read from one.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl SnippetPack {
+    /// Read a pack, as JSON if the file ends in `.json` and TOML otherwise.
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        let mut pack: Self = if is_json(path) {
+            serde_json::from_str(&contents)
+                .wrap_err_with(|| format!("Invalid snippet pack in {}", path.display()))?
+        } else {
+            toml::from_str(&contents)
+                .wrap_err_with(|| format!("Invalid snippet pack in {}", path.display()))?
+        };
+        if pack.format > PACK_FORMAT {
+            return Err(eyre!(
+                "{} uses pack format {}, but this version of uhh only understands up to {}",
+                path.display(),
+                pack.format,
+                PACK_FORMAT
+            ));
+        }
+        for snippet in &mut pack.snippets {
+            snippet.pack = Some(pack.name.clone());
+        }
+        pack.path = Some(path.to_path_buf());
+        Ok(pack)
+    }
+
+    /// Write the pack, as JSON if the path ends in `.json` and TOML otherwise.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            toml::to_string_pretty(self)?
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, contents)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Whether this pack's version is newer than `other`'s.
+    ///
+    /// Versions are compared numerically, component by component, so `1.10` is newer than `1.9`.
+    pub fn is_newer_than(&self, other: &SnippetPack) -> bool {
+        fn parse(version: &str) -> Vec<u64> {
+            version
+                .trim_start_matches('v')
+                .split('.')
+                .map(|part| part.parse().unwrap_or(0))
+                .collect()
+        }
+        parse(&self.version) > parse(&other.version)
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// The user's snippet library, stored as TOML in the data directory, along with any installed
+/// snippet packs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Library {
+    #[serde(default, rename = "snippet")]
+    pub snippets: Vec<Snippet>,
+    /// Installed packs. These are read only, and never written back to the library.
+    #[serde(skip)]
+    pub packs: Vec<SnippetPack>,
+    /// Problems with pack files that were skipped while loading.
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+impl Library {
+    /// Where the library is stored, e.g. `~/.local/share/uhh/snippets.toml` on Linux.
+    pub fn path() -> Result<PathBuf> {
+        dirs::data_dir()
+            .map(|dir| dir.join("uhh").join("snippets.toml"))
+            .ok_or_else(|| eyre!("Could not find a data directory for the snippet library"))
+    }
+
+    /// The directory packs are installed to, e.g. `~/.local/share/uhh/packs` on Linux.
+    ///
+    /// Pack files anywhere beneath it are loaded, so a team's repository of packs can simply be
+    /// cloned into it and kept up to date with `git pull`.
+    pub fn packs_dir() -> Result<PathBuf> {
+        Self::path().map(|path| path.with_file_name("packs"))
+    }
+
+    /// Load the library and installed packs. The library is empty until the first snippet is
+    /// saved.
+    pub fn load() -> Result<Self> {
+        let mut library = Self::load_from(&Self::path()?)?;
+        library.load_packs(&Self::packs_dir()?);
+        Ok(library)
+    }
+
+    /// Load every pack file beneath `dir`, skipping hidden directories such as `.git`.
+    fn load_packs(&mut self, dir: &Path) {
+        self.load_packs_in(dir, &mut HashSet::new());
+    }
+
+    /// Load the packs beneath `dir`, unless it has been visited already. Symlinked directories
+    /// are followed, so a checkout can be linked in, but a link back up the tree isn't.
+    fn load_packs_in(&mut self, dir: &Path, visited: &mut HashSet<PathBuf>) {
+        let Ok(canonical) = dir.canonicalize() else {
+            return;
+        };
+        if !visited.insert(canonical) {
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        paths.sort();
+        for path in paths {
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            let is_pack = path
+                .extension()
+                .is_some_and(|ext| ext == "toml" || ext == "json");
+            if hidden {
+                continue;
+            } else if path.is_dir() {
+                self.load_packs_in(&path, visited);
+            } else if is_pack {
+                match SnippetPack::read(&path) {
+                    Ok(pack) => self.packs.push(pack),
+                    Err(e) => self.warnings.push(e.to_string()),
+                }
+            }
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("Invalid snippets in {}", path.display()))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::path()?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Add a snippet, replacing any existing snippet with the same name.
+    pub fn add(&mut self, snippet: Snippet) {
+        self.remove(&snippet.name);
+        self.snippets.push(snippet);
+    }
+
+    /// Remove the snippet with the given name, returning whether there was one.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.snippets.len();
+        self.snippets.retain(|snippet| snippet.name != name);
+        self.snippets.len() != before
+    }
+
+    /// Every snippet, with those from packs first.
+    pub fn all(&self) -> impl Iterator<Item = &Snippet> {
+        self.packs
+            .iter()
+            .flat_map(|pack| &pack.snippets)
+            .chain(&self.snippets)
+    }
+
+    /// The snippets matching a query, see [`Snippet::matches`].
+    pub fn search(&self, query: &str) -> Vec<&Snippet> {
+        self.all()
+            .filter(|snippet| snippet.matches(query))
+            .collect()
+    }
+
+    /// Snippets similar to a natural language prompt, most similar first.
+    pub fn suggest(&self, prompt: &str, limit: usize) -> Vec<&Snippet> {
+        let snippets: Vec<&Snippet> = self.all().collect();
+        let texts: Vec<String> = snippets.iter().map(|s| s.search_text()).collect();
+        let index = Bm25::new(texts.iter().map(String::as_str));
+        index
+            .top(prompt, limit, 0.5)
+            .into_iter()
+            .map(|i| snippets[i])
+            .collect()
+    }
+
+    /// Install a pack into [`Library::packs_dir`], returning where it was installed.
+    pub fn install_pack(&self, pack: &SnippetPack, force: bool) -> Result<PathBuf> {
+        self.install_pack_in(&Self::packs_dir()?, pack, force)
+    }
+
+    /// Install a pack into `dir`, returning where it was installed.
+    ///
+    /// An installed pack with the same name is replaced, unless it has a newer version and
+    /// `force` isn't set. Packs that weren't installed by uhh, such as those in a cloned
+    /// repository, are never replaced, and neither is a file belonging to a different pack.
+    fn install_pack_in(&self, dir: &Path, pack: &SnippetPack, force: bool) -> Result<PathBuf> {
+        let file_name: String = pack
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path = dir.join(format!("{}.toml", file_name));
+
+        let installed = self.packs.iter().find(|p| p.name == pack.name);
+        if let Some(installed) = installed {
+            if installed.is_newer_than(pack) && !force {
+                return Err(eyre!(
+                    "Pack {} {} is already installed, which is newer than {}",
+                    installed.name,
+                    installed.version,
+                    pack.version
+                ));
+            }
+            if let Some(installed_path) = &installed.path
+                && installed_path != &path
+            {
+                return Err(eyre!(
+                    "Pack {} is already installed from {}, update it there instead",
+                    installed.name,
+                    installed_path.display()
+                ));
+            }
+        } else if let Some(other) = self.packs.iter().find(|p| p.path.as_ref() == Some(&path)) {
+            return Err(eyre!(
+                "Pack {} can't be installed to {}, which holds the {} pack",
+                pack.name,
+                path.display(),
+                other.name
+            ));
+        } else if path.exists() {
+            return Err(eyre!(
+                "Pack {} can't be installed to {}, which already exists",
+                pack.name,
+                path.display()
+            ));
+        }
+        pack.write(&path)?;
+        Ok(path)
+    }
+}
+
+/// Split "name #tag #tag" as typed in the save prompt into a name and tags.
+pub fn parse_name_and_tags(input: &str) -> (String, Vec<String>) {
+    let (tags, name): (Vec<&str>, Vec<&str>) = input
+        .split_whitespace()
+        .partition(|word| word.starts_with('#'));
+    let tags = tags
+        .into_iter()
+        .map(|tag| tag.trim_start_matches('#').to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    (name.join(" "), tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(name: &str, tags: &[&str], prompt: &str, command: &str) -> Snippet {
+        Snippet {
+            name: name.into(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            prompt: prompt.into(),
+            command: command.into(),
+            explanation: String::new(),
+            pack: None,
+        }
+    }
+
+    #[test]
+    fn parses_names_and_tags() {
+        assert_eq!(
+            parse_name_and_tags("biggest files #disk #Ops"),
+            ("biggest files".into(), vec!["disk".into(), "Ops".into()])
+        );
+        assert_eq!(
+            parse_name_and_tags("#disk  free space # "),
+            ("free space".into(), vec!["disk".into()])
+        );
+        assert_eq!(
+            parse_name_and_tags("#only"),
+            (String::new(), vec!["only".into()])
+        );
+    }
+
+    #[test]
+    fn searches_every_term() {
+        let mut library = Library::default();
+        library.add(snippet(
+            "biggest files",
+            &["disk"],
+            "",
+            "du -sh * | sort -h",
+        ));
+        library.add(snippet("ports", &["net"], "what is listening", "ss -tlnp"));
+
+        let names = |query: &str| -> Vec<String> {
+            library
+                .search(query)
+                .into_iter()
+                .map(|snippet| snippet.name.clone())
+                .collect()
+        };
+        assert_eq!(names("SORT"), ["biggest files"]);
+        assert_eq!(names("#disk du"), ["biggest files"]);
+        assert_eq!(names("#dis"), Vec::<String>::new());
+        assert_eq!(names("listening ss"), ["ports"]);
+        assert_eq!(names(""), ["biggest files", "ports"]);
+    }
+
+    #[test]
+    fn saves_and_loads_the_library() {
+        let path = std::env::temp_dir().join(format!(
+            "uhh-snippets-test-{}/snippets.toml",
+            std::process::id()
+        ));
+        assert!(Library::load_from(&path).unwrap().snippets.is_empty());
+
+        let mut library = Library::default();
+        let mut biggest = snippet("biggest", &["disk"], "biggest files", "du -sh * | sort -h");
+        biggest.explanation = "Sizes of everything here.".into();
+        library.add(biggest.clone());
+        library.add(snippet("ports", &[], "", "ss -tlnp"));
+        library.add(snippet("ports", &["net"], "", "ss -tlnp"));
+        library.save_to(&path).unwrap();
+
+        let loaded = Library::load_from(&path).unwrap();
+        assert_eq!(
+            loaded.snippets,
+            [biggest, snippet("ports", &["net"], "", "ss -tlnp")]
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    fn pack(name: &str, version: &str) -> SnippetPack {
+        SnippetPack {
+            format: PACK_FORMAT,
+            name: name.into(),
+            version: version.into(),
+            description: String::new(),
+            snippets: vec![snippet("ports", &["net"], "", "ss -tlnp")],
+            path: None,
+        }
+    }
+
+    /// A fresh directory for a test to write packs to.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("uhh-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reads_and_writes_packs() {
+        let dir = scratch("packs-test");
+        for file in ["team.toml", "team.json"] {
+            let path = dir.join(file);
+            pack("team", "1.0.0").write(&path).unwrap();
+            let read = SnippetPack::read(&path).unwrap();
+            assert_eq!(read.name, "team");
+            assert_eq!(read.path, Some(path));
+            assert_eq!(read.snippets[0].command, "ss -tlnp");
+            assert_eq!(read.snippets[0].pack.as_deref(), Some("team"));
+        }
+
+        let future = dir.join("future.toml");
+        let mut newer = pack("future", "1.0.0");
+        newer.format = PACK_FORMAT + 1;
+        newer.write(&future).unwrap();
+        let error = SnippetPack::read(&future).unwrap_err().to_string();
+        assert!(error.contains("uses pack format 2"), "{}", error);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert!(pack("a", "1.10.0").is_newer_than(&pack("a", "1.9.3")));
+        assert!(pack("a", "v2").is_newer_than(&pack("a", "1.9")));
+        assert!(pack("a", "1.0.1").is_newer_than(&pack("a", "1.0")));
+        assert!(!pack("a", "1.0.0").is_newer_than(&pack("a", "1.0.0")));
+        assert!(!pack("a", "").is_newer_than(&pack("a", "0.1")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops_are_loaded_once() {
+        let dir = scratch("symlink-test");
+        std::fs::create_dir_all(dir.join("team")).unwrap();
+        pack("team", "1.0.0")
+            .write(&dir.join("team/team.toml"))
+            .unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("team/loop")).unwrap();
+
+        let mut library = Library::default();
+        library.load_packs(&dir);
+        assert_eq!(library.packs.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn installs_packs_by_name() {
+        let dir = scratch("install-test");
+        let load = || {
+            let mut library = Library::default();
+            library.load_packs(&dir);
+            library
+        };
+
+        let installed = load()
+            .install_pack_in(&dir, &pack("a.b", "1.0.0"), false)
+            .unwrap();
+        assert_eq!(installed, dir.join("a_b.toml"));
+
+        // Only a newer version replaces it, unless forced.
+        let error = load()
+            .install_pack_in(&dir, &pack("a.b", "0.9.0"), false)
+            .unwrap_err();
+        assert!(error.to_string().contains("newer than 0.9.0"), "{}", error);
+        load()
+            .install_pack_in(&dir, &pack("a.b", "0.9.0"), true)
+            .unwrap();
+        load()
+            .install_pack_in(&dir, &pack("a.b", "1.1.0"), false)
+            .unwrap();
+        assert_eq!(load().packs.len(), 1);
+        assert_eq!(load().packs[0].version, "1.1.0");
+
+        // A different pack whose file name would be the same is refused.
+        let error = load()
+            .install_pack_in(&dir, &pack("a_b", "1.0.0"), false)
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("holds the a.b pack"),
+            "{}",
+            error
+        );
+
+        // As is a second copy of a pack from a cloned repository.
+        pack("ops", "1.0.0")
+            .write(&dir.join("team-repo/ops.toml"))
+            .unwrap();
+        let error = load()
+            .install_pack_in(&dir, &pack("ops", "1.1.0"), false)
+            .unwrap_err();
+        assert!(error.to_string().contains("update it there"), "{}", error);
+        assert_eq!(load().packs.len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
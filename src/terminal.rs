@@ -0,0 +1,20 @@
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::{
+    event::{DisableBracketedPaste, EnableBracketedPaste},
+    execute,
+};
+
+/// Set up the terminal for the app, with bracketed paste, so pasted text arrives as one paste
+/// event rather than as keys, and a pasted newline doesn't submit the prompt.
+pub fn init() -> DefaultTerminal {
+    let terminal = ratatui::init();
+    // Terminals without bracketed paste still work, pastes just arrive as keys.
+    let _ = execute!(std::io::stdout(), EnableBracketedPaste);
+    terminal
+}
+
+/// Put the terminal back as it was, e.g. before running an editor or a command.
+pub fn restore() {
+    let _ = execute!(std::io::stdout(), DisableBracketedPaste);
+    ratatui::restore();
+}
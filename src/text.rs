@@ -0,0 +1,283 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// An editable string with a cursor.
+///
+/// The cursor is a byte offset into the text which always sits on a grapheme cluster boundary, so
+/// edits never split a multi-byte character or a combined sequence such as an emoji with modifiers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextBuffer {
+    text: String,
+    cursor: usize,
+}
+
+impl TextBuffer {
+    /// Constructs a new, empty [`TextBuffer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current contents of the buffer.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The byte offset of the cursor.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replace the contents of the buffer, placing the cursor at the end.
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.cursor = self.text.len();
+    }
+
+    /// Move the cursor to the given byte offset, snapping back to the nearest grapheme boundary.
+    pub fn set_cursor(&mut self, cursor: usize) {
+        self.cursor = if cursor >= self.text.len() {
+            self.text.len()
+        } else {
+            self.text
+                .grapheme_indices(true)
+                .map(|(i, _)| i)
+                .take_while(|&i| i <= cursor)
+                .last()
+                .unwrap_or(0)
+        };
+    }
+
+    /// Insert a character at the cursor.
+    pub fn insert_char(&mut self, c: char) {
+        let mut buf = [0; 4];
+        self.insert_str(c.encode_utf8(&mut buf));
+    }
+
+    /// Insert a string at the cursor, leaving the cursor after the inserted text.
+    ///
+    /// Inserted characters may combine with the grapheme before the cursor (e.g. a combining
+    /// accent), in which case the cursor is snapped to the end of the combined cluster.
+    pub fn insert_str(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        let end = self.cursor + s.len();
+        self.cursor = self.next_boundary_from(end);
+    }
+
+    /// Delete the grapheme before the cursor.
+    pub fn backspace(&mut self) {
+        let start = self.prev_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        // Removing a grapheme can merge its neighbours into a single cluster.
+        self.set_cursor(start);
+    }
+
+    /// Delete the grapheme under the cursor.
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+        self.set_cursor(self.cursor);
+    }
+
+    /// Move the cursor one grapheme to the left.
+    pub fn move_left(&mut self) {
+        self.cursor = self.prev_boundary();
+    }
+
+    /// Move the cursor one grapheme to the right.
+    pub fn move_right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    /// Move the cursor to the start of the current line.
+    pub fn move_home(&mut self) {
+        let start = self.text[..self.cursor].rfind('\n').map_or(0, |i| i + 1);
+        self.set_cursor(start);
+    }
+
+    /// Move the cursor to the end of the current line.
+    pub fn move_end(&mut self) {
+        let end = self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.cursor + i);
+        self.set_cursor(end);
+    }
+
+    /// Move the cursor up (negative) or down (positive) by a number of lines, keeping its display
+    /// column where the target line is long enough.
+    pub fn move_lines(&mut self, delta: isize) {
+        let (row, col) = self.cursor_position();
+        let target = row.saturating_add_signed(delta);
+        let Some(line_start) = self.line_starts().nth(target).or_else(|| {
+            // Past the last line, clamp to it.
+            (delta > 0).then(|| self.line_starts().last()).flatten()
+        }) else {
+            return;
+        };
+
+        let line = self.text[line_start..]
+            .split('\n')
+            .next()
+            .unwrap_or_default();
+        let mut offset = line_start;
+        let mut width = 0;
+        for grapheme in line.graphemes(true) {
+            width += grapheme.width();
+            if width > col {
+                break;
+            }
+            offset += grapheme.len();
+        }
+        self.set_cursor(offset);
+    }
+
+    /// The zero-based line and display column of the cursor.
+    ///
+    /// The column is measured in terminal cells, so wide characters such as CJK or emoji count
+    /// for two.
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let before = &self.text[..self.cursor];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let row = before.matches('\n').count();
+        (row, before[line_start..].width())
+    }
+
+    /// The text with `marker` inserted at the cursor, for rendering.
+    pub fn with_cursor(&self, marker: char) -> String {
+        let mut display_text = self.text.clone();
+        display_text.insert(self.cursor, marker);
+        display_text
+    }
+
+    fn line_starts(&self) -> impl Iterator<Item = usize> + '_ {
+        std::iter::once(0).chain(self.text.match_indices('\n').map(|(i, _)| i + 1))
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+
+    /// The first grapheme boundary at or after `offset`, where `offset` is a char boundary.
+    fn next_boundary_from(&self, offset: usize) -> usize {
+        self.text
+            .grapheme_indices(true)
+            .map(|(i, _)| i)
+            .find(|&i| i >= offset)
+            .unwrap_or(self.text.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Debug, Clone)]
+    enum Edit {
+        Insert(char),
+        Paste(String),
+        Backspace,
+        Delete,
+        Left,
+        Right,
+        Home,
+        End,
+        Set(String),
+        Jump(usize),
+        Lines(isize),
+    }
+
+    fn edit() -> impl Strategy<Value = Edit> {
+        prop_oneof![
+            any::<char>().prop_map(Edit::Insert),
+            "\\PC{0,8}".prop_map(Edit::Paste),
+            Just(Edit::Backspace),
+            Just(Edit::Delete),
+            Just(Edit::Left),
+            Just(Edit::Right),
+            Just(Edit::Home),
+            Just(Edit::End),
+            "(.|\r|\n){0,16}".prop_map(Edit::Set),
+            any::<usize>().prop_map(Edit::Jump),
+            (-3isize..=3).prop_map(Edit::Lines),
+        ]
+    }
+
+    fn apply(buffer: &mut TextBuffer, edit: Edit) {
+        match edit {
+            Edit::Insert(c) => buffer.insert_char(c),
+            Edit::Paste(s) => buffer.insert_str(&s),
+            Edit::Backspace => buffer.backspace(),
+            Edit::Delete => buffer.delete(),
+            Edit::Left => buffer.move_left(),
+            Edit::Right => buffer.move_right(),
+            Edit::Home => buffer.move_home(),
+            Edit::End => buffer.move_end(),
+            Edit::Set(s) => buffer.set_text(s),
+            Edit::Jump(i) => buffer.set_cursor(i),
+            Edit::Lines(delta) => buffer.move_lines(delta),
+        }
+    }
+
+    fn is_grapheme_boundary(text: &str, offset: usize) -> bool {
+        offset == text.len() || text.grapheme_indices(true).any(|(i, _)| i == offset)
+    }
+
+    proptest! {
+        #[test]
+        fn random_edits_keep_cursor_on_grapheme_boundary(edits in prop::collection::vec(edit(), 0..64)) {
+            let mut buffer = TextBuffer::new();
+            for edit in edits {
+                apply(&mut buffer, edit);
+                prop_assert!(buffer.cursor() <= buffer.text().len());
+                prop_assert!(is_grapheme_boundary(buffer.text(), buffer.cursor()));
+                let _ = buffer.cursor_position();
+                let _ = buffer.with_cursor('█');
+            }
+        }
+    }
+
+    #[test]
+    fn multi_byte_characters_move_as_one() {
+        let mut buffer = TextBuffer::new();
+        buffer.insert_char('é');
+        buffer.insert_str("👍🏽");
+        assert_eq!(buffer.text(), "é👍🏽");
+        buffer.move_left();
+        assert_eq!(buffer.cursor(), 'é'.len_utf8());
+        buffer.backspace();
+        assert_eq!(buffer.text(), "👍🏽");
+        buffer.delete();
+        assert_eq!(buffer.text(), "");
+    }
+
+    #[test]
+    fn cursor_position_counts_display_width() {
+        let mut buffer = TextBuffer::new();
+        buffer.set_text("ls\n日本語");
+        assert_eq!(buffer.cursor_position(), (1, 6));
+        buffer.move_home();
+        assert_eq!(buffer.cursor_position(), (1, 0));
+    }
+
+    #[test]
+    fn move_lines_keeps_display_column() {
+        let mut buffer = TextBuffer::new();
+        buffer.set_text("日本語\nab\nabcdef");
+        buffer.move_lines(-2);
+        assert_eq!(buffer.cursor_position(), (0, 6));
+        buffer.move_lines(1);
+        assert_eq!(buffer.cursor_position(), (1, 2));
+        buffer.move_lines(5);
+        assert_eq!(buffer.cursor_position(), (2, 2));
+    }
+}
@@ -0,0 +1,145 @@
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+/// The built-in themes, selected with `theme = "..."` in the config file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    NoColor,
+}
+
+/// The styles used to render the UI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// The border of the focused pane.
+    pub focused: Style,
+    /// The border of a pane waiting on the model.
+    pub loading: Style,
+    /// The review pane when the command looks safe.
+    pub safe: Style,
+    /// The review pane when the command may be unsafe.
+    pub danger: Style,
+    /// The status line when idle or the command looks safe.
+    pub status: Style,
+    /// The status line while waiting on the model.
+    pub status_busy: Style,
+    /// The status line when the command may be unsafe.
+    pub status_danger: Style,
+    /// Keys listed in the help overlay.
+    pub help_key: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    /// Resolve a theme by name, honouring `NO_COLOR` (see <https://no-color.org>) over the config.
+    pub fn load(name: ThemeName) -> Self {
+        Self::resolve(name, std::env::var_os("NO_COLOR").as_deref())
+    }
+
+    /// Resolve a theme by name, given the value of `NO_COLOR`, which any non-empty value sets.
+    fn resolve(name: ThemeName, no_color: Option<&std::ffi::OsStr>) -> Self {
+        if no_color.is_some_and(|value| !value.is_empty()) {
+            return Self::no_color();
+        }
+        match name {
+            ThemeName::Dark => Self::dark(),
+            ThemeName::Light => Self::light(),
+            ThemeName::HighContrast => Self::high_contrast(),
+            ThemeName::NoColor => Self::no_color(),
+        }
+    }
+
+    /// The original palette, for terminals with a dark background.
+    pub fn dark() -> Self {
+        Self {
+            focused: Style::default().fg(Color::Yellow),
+            loading: Style::default().fg(Color::Cyan),
+            safe: Style::default().fg(Color::Green),
+            danger: Style::default().fg(Color::Red),
+            status: Style::default().bg(Color::Green).fg(Color::Black),
+            status_busy: Style::default().bg(Color::Yellow).fg(Color::Black),
+            status_danger: Style::default().bg(Color::Red).fg(Color::Black),
+            help_key: Style::default().fg(Color::Yellow),
+        }
+    }
+
+    /// Darker colours which stay legible on a light background.
+    pub fn light() -> Self {
+        Self {
+            focused: Style::default().fg(Color::Blue),
+            loading: Style::default().fg(Color::Magenta),
+            safe: Style::default().fg(Color::Green),
+            danger: Style::default().fg(Color::Red),
+            status: Style::default().bg(Color::Green).fg(Color::White),
+            status_busy: Style::default().bg(Color::Blue).fg(Color::White),
+            status_danger: Style::default().bg(Color::Red).fg(Color::White),
+            help_key: Style::default().fg(Color::Blue),
+        }
+    }
+
+    /// Bright colours and bold text for maximum contrast.
+    pub fn high_contrast() -> Self {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        Self {
+            focused: bold.fg(Color::LightYellow),
+            loading: bold.fg(Color::LightCyan),
+            safe: bold.fg(Color::LightGreen),
+            danger: bold.fg(Color::LightRed),
+            status: bold.bg(Color::White).fg(Color::Black),
+            status_busy: bold.bg(Color::LightYellow).fg(Color::Black),
+            status_danger: bold.bg(Color::LightRed).fg(Color::Black),
+            help_key: bold.fg(Color::LightYellow),
+        }
+    }
+
+    /// No colours at all, using text attributes to tell states apart.
+    pub fn no_color() -> Self {
+        let plain = Style::default();
+        Self {
+            focused: plain.add_modifier(Modifier::BOLD),
+            loading: plain.add_modifier(Modifier::DIM),
+            safe: plain,
+            danger: plain.add_modifier(Modifier::BOLD),
+            status: plain.add_modifier(Modifier::REVERSED),
+            status_busy: plain.add_modifier(Modifier::REVERSED | Modifier::DIM),
+            status_danger: plain.add_modifier(Modifier::REVERSED | Modifier::BOLD),
+            help_key: plain.add_modifier(Modifier::BOLD),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn no_color_overrides_the_configured_theme() {
+        for name in [
+            ThemeName::Dark,
+            ThemeName::Light,
+            ThemeName::HighContrast,
+            ThemeName::NoColor,
+        ] {
+            assert_eq!(
+                Theme::resolve(name, Some(OsStr::new("1"))),
+                Theme::no_color()
+            );
+        }
+        // An empty NO_COLOR is the same as none at all.
+        assert_eq!(
+            Theme::resolve(ThemeName::Light, Some(OsStr::new(""))),
+            Theme::light()
+        );
+        assert_eq!(Theme::resolve(ThemeName::Dark, None), Theme::dark());
+    }
+}
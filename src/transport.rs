@@ -0,0 +1,290 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use futures::future::BoxFuture;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::infer::CompletionRequest;
+
+/// A response from the provider: the HTTP status and the raw body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl RawResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// How requests reach the provider.
+///
+/// This is the only way [`crate::infer::InferenceEngine`] talks to the network, so swapping it
+/// out lets the rest of the app run offline, e.g. against a recorded [`Cassette`].
+pub trait Transport: Debug + Send + Sync {
+    fn send<'a>(&'a self, request: &'a CompletionRequest) -> BoxFuture<'a, Result<RawResponse>>;
+}
+
+/// How long to wait for the provider before giving up on a request.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The longest a `Retry-After` header is honoured for, so a provider can't stall the app.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// Sends requests to an OpenAI compatible chat completions API.
+///
+/// Requests that fail to connect, are rate limited or hit a server error are retried with
+/// exponential backoff. A request that times out isn't, as the provider may have gone on to
+/// answer it, and bill for it, regardless.
+#[derive(Debug)]
+pub struct HttpTransport {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    retries: u32,
+    backoff: Duration,
+    timeout: Duration,
+}
+
+impl HttpTransport {
+    pub fn new(api_key: String, base_url: String) -> Result<Self> {
+        let client = Client::builder()
+            .build()
+            .wrap_err("Failed to set up the HTTP client")?;
+        Ok(Self {
+            client,
+            api_key,
+            base_url,
+            retries: 2,
+            backoff: Duration::from_millis(500),
+            timeout: TIMEOUT,
+        })
+    }
+
+    /// Give up on requests that take longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry failed requests up to `retries` times, waiting `backoff` before the first retry
+    /// and doubling it for each one after.
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    async fn send_once(&self, request: &CompletionRequest) -> Result<Attempt> {
+        let url = format!("{}/chat/completions", self.base_url);
+        let result = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .timeout(self.timeout)
+            .json(request)
+            .send()
+            .await;
+        let response = match result {
+            Ok(response) => response,
+            // Only a request that never reached the provider is safe to send again.
+            Err(e) if e.is_connect() => return Ok(Attempt::Retry(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .map(|secs| Duration::from_secs(secs).min(MAX_RETRY_AFTER));
+        let body = response.text().await?;
+        let response = RawResponse { status, body };
+        if status == 429 || (500..600).contains(&status) {
+            Ok(Attempt::RetryResponse(response, retry_after))
+        } else {
+            Ok(Attempt::Done(response))
+        }
+    }
+}
+
+/// The outcome of a single attempt at a request.
+enum Attempt {
+    Done(RawResponse),
+    /// The request never reached the provider, and is worth retrying.
+    Retry(color_eyre::Report),
+    /// A response with a status worth retrying, after the delay the provider asked for, if any.
+    /// It is returned as is if the retries run out.
+    RetryResponse(RawResponse, Option<Duration>),
+}
+
+impl Transport for HttpTransport {
+    fn send<'a>(&'a self, request: &'a CompletionRequest) -> BoxFuture<'a, Result<RawResponse>> {
+        Box::pin(async move {
+            let mut backoff = self.backoff;
+            for attempt in 0..=self.retries {
+                let last = attempt == self.retries;
+                let delay = match self.send_once(request).await? {
+                    Attempt::Done(response) => return Ok(response),
+                    Attempt::Retry(e) if last => return Err(e),
+                    Attempt::RetryResponse(response, _) if last => return Ok(response),
+                    Attempt::Retry(_) => None,
+                    Attempt::RetryResponse(_, delay) => delay,
+                };
+                tokio::time::sleep(delay.unwrap_or(backoff)).await;
+                backoff *= 2;
+            }
+            unreachable!("the last attempt always returns")
+        })
+    }
+}
+
+/// A request and the response it got, as stored in a cassette.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: CompletionRequest,
+    pub status: u16,
+    /// The response body, as JSON if it was valid JSON, otherwise as a string.
+    pub response: serde_json::Value,
+}
+
+impl Interaction {
+    fn new(request: &CompletionRequest, response: &RawResponse) -> Self {
+        Self {
+            request: request.clone(),
+            status: response.status,
+            response: serde_json::from_str(&response.body)
+                .unwrap_or_else(|_| serde_json::Value::String(response.body.clone())),
+        }
+    }
+
+    fn raw_response(&self) -> RawResponse {
+        let body = match &self.response {
+            serde_json::Value::String(body) => body.clone(),
+            body => body.to_string(),
+        };
+        RawResponse {
+            status: self.status,
+            body,
+        }
+    }
+
+    /// What requests are matched on when replaying: the last message, which holds the user's
+    /// prompt or the command being checked.
+    ///
+    /// Matching on that alone means cassettes survive changes to the system prompts.
+    fn matches(&self, request: &CompletionRequest) -> bool {
+        self.request.model == request.model
+            && self.request.messages.last().map(|m| &m.content)
+                == request.messages.last().map(|m| &m.content)
+    }
+}
+
+/// Recorded interactions with the provider, stored as a JSON file.
+///
+/// Requests are recorded after redaction, so cassettes never contain secrets.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read cassette {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("Invalid cassette in {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .wrap_err_with(|| format!("Failed to write cassette {}", path.display()))
+    }
+}
+
+/// Passes requests on to another transport, recording each interaction to a cassette.
+#[derive(Debug)]
+pub struct Recorder {
+    inner: Arc<dyn Transport>,
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+}
+
+impl Recorder {
+    /// Record to `path`, replacing anything already recorded there.
+    pub fn new(inner: Arc<dyn Transport>, path: PathBuf) -> Self {
+        Self {
+            inner,
+            path,
+            cassette: Mutex::default(),
+        }
+    }
+}
+
+impl Transport for Recorder {
+    fn send<'a>(&'a self, request: &'a CompletionRequest) -> BoxFuture<'a, Result<RawResponse>> {
+        Box::pin(async move {
+            let response = self.inner.send(request).await?;
+            let mut cassette = self.cassette.lock().unwrap_or_else(|e| e.into_inner());
+            cassette
+                .interactions
+                .push(Interaction::new(request, &response));
+            // Save after every interaction, as the app usually exits straight after running a
+            // command, without returning here.
+            cassette.save(&self.path)?;
+            Ok(response)
+        })
+    }
+}
+
+/// Answers requests from a cassette, without touching the network.
+///
+/// Each interaction is replayed once, in the order they were recorded.
+#[derive(Debug)]
+pub struct Replayer {
+    interactions: Mutex<VecDeque<Interaction>>,
+}
+
+impl Replayer {
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            interactions: Mutex::new(cassette.interactions.into()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Cassette::load(path).map(Self::new)
+    }
+}
+
+impl Transport for Replayer {
+    fn send<'a>(&'a self, request: &'a CompletionRequest) -> BoxFuture<'a, Result<RawResponse>> {
+        Box::pin(async move {
+            let mut interactions = self.interactions.lock().unwrap_or_else(|e| e.into_inner());
+            let index = interactions
+                .iter()
+                .position(|interaction| interaction.matches(request))
+                .ok_or_else(|| {
+                    eyre!(
+                        "No recorded response for {:?}",
+                        request.messages.last().map_or("", |m| m.content.as_str())
+                    )
+                })?;
+            let interaction = interactions.remove(index).expect("index is in range");
+            Ok(interaction.raw_response())
+        })
+    }
+}
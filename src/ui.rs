@@ -1,15 +1,38 @@
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
-    widgets::{Block, BorderType, Paragraph, Widget},
+    layout::{Constraint, Direction, Flex, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Clear, Paragraph, Widget, Wrap},
 };
 
 use crate::app::{App, SafetyStatus};
+use crate::audit::Exchange;
+use crate::browser::SnippetBrowser;
+use crate::form::PlaceholderForm;
+use crate::inputs::{InputKind, Inputs};
+use crate::keymap::{Action, Keymap, Mode};
+use crate::text::TextBuffer;
+use crate::theme::Theme;
 
 impl Widget for &App {
     /// Renders the user interface widgets.
     fn render(self, area: Rect, buf: &mut Buffer) {
+        // Inputs get a panel down the right, beside the panes but above the status line.
+        let inputs = self.client.inputs();
+        let panes_area = if inputs.is_empty() {
+            area
+        } else {
+            let [panes_area, inputs_area] =
+                Layout::horizontal([Constraint::Min(0), Constraint::Percentage(30)]).areas(area);
+            let inputs_area = Rect {
+                height: inputs_area.height.saturating_sub(1),
+                ..inputs_area
+            };
+            render_inputs(inputs, &self.theme, inputs_area, buf);
+            panes_area
+        };
+
         let main_layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -18,104 +41,494 @@ impl Widget for &App {
                 Constraint::Ratio(1, 4), // Bottom pane (1 part of 4)
                 Constraint::Length(1),   // Status line (fixed 1 line)
             ])
-            .split(area);
+            .split(panes_area);
 
         // Top pane - editable input
         let top_block = Block::bordered()
             .title("Input")
             .border_type(BorderType::Rounded)
             .style(if self.focused_pane == 0 {
-                Style::default().fg(Color::Yellow)
+                self.theme.focused
             } else {
                 Style::default()
             });
 
-        let top_text = if self.focused_pane == 0 {
-            // Show cursor when focused
-            let mut display_text = self.input_text.clone();
-            display_text.insert(self.input_cursor, '█');
-            display_text
-        } else {
-            self.input_text.clone()
-        };
-
-        let top_paragraph = Paragraph::new(top_text.as_str()).block(top_block);
-
-        top_paragraph.render(main_layout[0], buf);
+        render_text_buffer(
+            &self.input,
+            self.focused_pane == 0,
+            top_block,
+            main_layout[0],
+            buf,
+        );
 
         // Middle pane - twice the size
         let middle_block = Block::bordered()
-            .title("Generated command")
+            .title(if self.script_mode {
+                "Generated script"
+            } else {
+                "Generated command"
+            })
             .border_type(BorderType::Rounded)
             .style(if self.is_loading_completion {
-                Style::default().fg(Color::Cyan)
+                self.theme.loading
             } else if self.focused_pane == 1 {
-                Style::default().fg(Color::Yellow)
+                self.theme.focused
             } else {
                 Style::default()
             });
 
-        // TODO:Refactor?
-        let middle_text = if self.focused_pane == 1 {
-            // Show cursor when focused
-            let mut display_text = self.response_text.clone();
-            display_text.insert(self.response_cursor, '█');
-            display_text
-        } else {
-            self.response_text.clone()
-        };
-
-        let middle_paragraph = Paragraph::new(middle_text.as_str()).block(middle_block);
-
-        middle_paragraph.render(main_layout[1], buf);
+        render_text_buffer(
+            &self.response,
+            self.focused_pane == 1,
+            middle_block,
+            main_layout[1],
+            buf,
+        );
 
         let bottom_block = Block::bordered()
             .title("Command review")
             .border_type(BorderType::Rounded)
             .style(if self.is_loading_safety_check {
-                Style::default().fg(Color::Cyan)
+                self.theme.loading
             } else {
                 match self.safety_status {
-                    SafetyStatus::Safe => Style::default().fg(Color::Green),
-                    SafetyStatus::Unsafe => Style::default().fg(Color::Red),
+                    SafetyStatus::Safe => self.theme.safe,
+                    SafetyStatus::Unsafe => self.theme.danger,
                     SafetyStatus::Unknown => Style::default(),
                 }
             });
 
-        let bottom_paragraph = Paragraph::new(self.safety_check_text.as_str()).block(bottom_block);
+        let review_text = [
+            self.commentary_text.as_str(),
+            self.script_check_text.as_str(),
+            self.safety_check_text.as_str(),
+        ]
+        .into_iter()
+        .filter(|section| !section.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+        let bottom_paragraph = Paragraph::new(review_text).block(bottom_block);
 
         bottom_paragraph.render(main_layout[2], buf);
 
         // Status line with dynamic content
-        let status_text = if self.is_loading_completion {
-            "Loading completion... | Press Up/Down to navigate, Esc to quit"
+        let status = if let Some(notice) = &self.notice {
+            notice.as_str()
+        } else if self.is_loading_completion {
+            "Loading completion..."
         } else if self.is_loading_safety_check {
-            "Running safety check... | Press Up/Down to navigate, Esc to quit"
+            "Running safety check..."
         } else {
             match self.safety_status {
-                SafetyStatus::Safe => {
-                    "Command appears safe | Press Up/Down to navigate, Esc to quit"
-                }
-                SafetyStatus::Unsafe => {
-                    "⚠️ Command may be unsafe | Press Up/Down to navigate, Esc to quit"
-                }
-                SafetyStatus::Unknown => "Ready | Press Up/Down to navigate, Esc to quit",
+                SafetyStatus::Safe => "Command appears safe",
+                SafetyStatus::Unsafe => "⚠️ Command may be unsafe",
+                SafetyStatus::Unknown => "Ready",
             }
         };
+        let mode = match (self.keymap.vi, self.mode) {
+            (false, _) => "",
+            (true, Mode::Insert) => "-- INSERT -- ",
+            (true, Mode::Normal) => "-- NORMAL -- ",
+        };
+        let help = match self.keymap.first_key(self.mode, Action::ToggleHelp) {
+            Some(key) => format!(" | Press {} for help", key),
+            None => String::new(),
+        };
+        let kind = if self.script_mode { "[script] " } else { "" };
+        let cached = match (self.completion_cached, self.safety_check_cached) {
+            (true, true) => "[cached] ",
+            (true, false) => "[cached command] ",
+            (false, true) => "[cached review] ",
+            (false, false) => "",
+        };
+        let meter = self.client.meter();
+        let session = meter.session();
+        let usage = if session.requests == 0 {
+            String::new()
+        } else {
+            let unpriced = if meter.has_unpriced() { "+" } else { "" };
+            format!(
+                " | {} tokens, ${:.4}{}",
+                session.tokens(),
+                session.cost,
+                unpriced
+            )
+        };
+        let budget = match meter.budget_warning() {
+            Some(warning) => format!(" | {}", warning),
+            None => String::new(),
+        };
+        let audit = match self.client.audit_failure() {
+            Some(failure) => format!(" | Audit log failed: {}", failure),
+            None => String::new(),
+        };
+        let status_text = format!(
+            "{}{}{}{}{}{}{}{}",
+            mode, kind, cached, status, usage, budget, audit, help
+        );
 
-        let status_color = if self.is_loading_completion || self.is_loading_safety_check {
-            Color::Yellow
+        let status_style = if !budget.is_empty() || !audit.is_empty() {
+            self.theme.status_danger
+        } else if self.is_loading_completion || self.is_loading_safety_check {
+            self.theme.status_busy
         } else {
             match self.safety_status {
-                SafetyStatus::Safe => Color::Green,
-                SafetyStatus::Unsafe => Color::Red,
-                SafetyStatus::Unknown => Color::Green,
+                SafetyStatus::Safe => self.theme.status,
+                SafetyStatus::Unsafe => self.theme.status_danger,
+                SafetyStatus::Unknown => self.theme.status,
+            }
+        };
+
+        let status_paragraph = Paragraph::new(status_text).style(status_style);
+
+        let status_area = Rect {
+            width: area.width,
+            ..main_layout[3]
+        };
+        status_paragraph.render(status_area, buf);
+
+        if let Some(form) = &self.form {
+            render_form(form, &self.theme, area, buf);
+        }
+
+        if let Some(browser) = &self.browser {
+            render_browser(browser, &self.theme, area, buf);
+        }
+
+        if let Some((prompt, text)) = &self.line_prompt {
+            render_line_prompt(prompt.title(), text, &self.theme, area, buf);
+        }
+
+        if self.show_debug {
+            let exchanges = self.client.recent_exchanges();
+            render_debug(&exchanges, self.debug_scroll, &self.theme, area, buf);
+        }
+
+        if self.show_help {
+            render_help(&self.keymap, &self.theme, area, buf);
+        }
+    }
+}
+
+/// Renders the side panel listing the inputs, and the files each one resolved to.
+fn render_inputs(inputs: &Inputs, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    let mut lines: Vec<Line> = Vec::new();
+    for input in &inputs.inputs {
+        let kind = match input.kind {
+            InputKind::File => "",
+            InputKind::Directory => " (directory)",
+            InputKind::Glob => " (glob)",
+            InputKind::Missing => " (not found)",
+        };
+        lines.push(Line::from(vec![
+            Span::styled(input.given.as_str(), theme.help_key),
+            Span::raw(kind),
+        ]));
+        if matches!(input.kind, InputKind::Directory | InputKind::Glob) {
+            lines.extend(
+                input
+                    .files
+                    .iter()
+                    .map(|file| Line::raw(format!("  {}", input.short_name(file).display()))),
+            );
+        }
+    }
+
+    // The panel doesn't scroll, so say how much of a long list is cut off.
+    let height = area.height.saturating_sub(2) as usize;
+    if lines.len() > height && height > 0 {
+        let hidden = lines.len() - (height - 1);
+        lines.truncate(height - 1);
+        lines.push(Line::raw(format!("…and {} more", hidden)));
+    }
+
+    let files = inputs.files().len();
+    let block = Block::bordered()
+        .title(format!(
+            "Inputs ({} file{})",
+            files,
+            if files == 1 { "" } else { "s" }
+        ))
+        .border_type(BorderType::Rounded);
+    Paragraph::new(lines).block(block).render(area, buf);
+}
+
+/// Renders the form for filling in a command's placeholders.
+fn render_form(form: &PlaceholderForm, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    let [area] = Layout::horizontal([Constraint::Percentage(70)])
+        .flex(Flex::Center)
+        .areas(area);
+    let height = form.fields.len() as u16 + 2 + if form.message.is_some() { 2 } else { 0 };
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+
+    let mut lines: Vec<Line> = form
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, (placeholder, value))| {
+            let label = format!("{} ({}): ", placeholder.name, placeholder.kind.name());
+            if i == form.focused {
+                Line::from(vec![
+                    Span::styled(label, theme.focused),
+                    Span::raw(value.with_cursor('█')),
+                ])
+            } else {
+                Line::from(vec![Span::raw(label), Span::raw(value.text().to_string())])
             }
+        })
+        .collect();
+    if let Some(message) = &form.message {
+        lines.push(Line::default());
+        lines.push(Line::styled(message.as_str(), theme.danger));
+    }
+
+    let block = Block::bordered()
+        .title("Fill in the command (Enter to run, Esc to cancel)")
+        .border_type(BorderType::Rounded);
+
+    Clear.render(area, buf);
+    Paragraph::new(lines).block(block).render(area, buf);
+}
+
+/// Renders the snippet library browser: a search box above the matching snippets.
+fn render_browser(browser: &SnippetBrowser, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    let [area] = Layout::horizontal([Constraint::Percentage(80)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(80)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [search_area, list_area] =
+        Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(area);
+
+    Clear.render(area, buf);
+
+    let search_title = if browser.suggesting_for.is_some() {
+        "Similar snippets were found, type to search instead"
+    } else {
+        "Search snippets (#tag to filter by tag)"
+    };
+    let search_block = Block::bordered()
+        .title(search_title)
+        .border_type(BorderType::Rounded)
+        .style(theme.focused);
+    render_text_buffer(&browser.query, true, search_block, search_area, buf);
+
+    let matches = browser.matches();
+    let mut lines: Vec<Line> = Vec::new();
+    if let Some(message) = &browser.message {
+        lines.push(Line::styled(message.as_str(), theme.danger));
+    }
+    if matches.is_empty() && browser.suggesting_for.is_none() {
+        lines.push(Line::raw(
+            "No matching snippets. Save one with the save snippet key.",
+        ));
+    }
+    for (i, snippet) in matches.iter().enumerate() {
+        let name_style = if i == browser.selected {
+            theme.focused.add_modifier(Modifier::REVERSED)
+        } else {
+            theme.help_key
+        };
+        let tags = snippet
+            .tags
+            .iter()
+            .map(|tag| format!("#{}", tag))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let pack = match &snippet.pack {
+            Some(pack) => format!(" [{}]", pack),
+            None => String::new(),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(snippet.name.as_str(), name_style),
+            Span::styled(pack, theme.status),
+            Span::raw(" "),
+            Span::raw(tags),
+        ]));
+        lines.push(Line::raw(format!("  {}", snippet.command)));
+    }
+    if browser.suggesting_for.is_some() {
+        let style = if browser.selected == matches.len() {
+            theme.focused.add_modifier(Modifier::REVERSED)
+        } else {
+            theme.help_key
         };
+        lines.push(Line::styled("Ask the model instead", style));
+        lines.push(Line::raw("  Generate a new command for the prompt"));
+    }
 
-        let status_paragraph =
-            Paragraph::new(status_text).style(Style::default().bg(status_color).fg(Color::Black));
+    // Keep the selection in view, each snippet takes two lines.
+    let visible = list_area.height.saturating_sub(2) as usize;
+    let scroll = (browser.selected * 2 + 2).saturating_sub(visible);
+
+    let list_block = Block::bordered()
+        .title("Enter to recall, Ctrl+D to delete, Esc to close")
+        .border_type(BorderType::Rounded);
+    Paragraph::new(lines)
+        .block(list_block)
+        .scroll((scroll as u16, 0))
+        .render(list_area, buf);
+}
 
-        status_paragraph.render(main_layout[3], buf);
+/// Renders a single line prompt, such as where to save a generated script.
+fn render_line_prompt(title: &str, text: &TextBuffer, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    let [area] = Layout::horizontal([Constraint::Percentage(60)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(3)])
+        .flex(Flex::Center)
+        .areas(area);
+
+    let block = Block::bordered()
+        .title(title)
+        .border_type(BorderType::Rounded)
+        .style(theme.focused);
+
+    Clear.render(area, buf);
+    render_text_buffer(text, true, block, area, buf);
+}
+
+/// Renders the debug pane, showing recent exchanges with the model exactly as they were sent.
+fn render_debug(exchanges: &[Exchange], scroll: u16, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    let [area] = Layout::horizontal([Constraint::Percentage(90)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(90)])
+        .flex(Flex::Center)
+        .areas(area);
+
+    let mut lines: Vec<Line> = Vec::new();
+    if exchanges.is_empty() {
+        lines.push(Line::raw("Nothing has been sent to the model yet."));
+    }
+    for exchange in exchanges {
+        if !lines.is_empty() {
+            lines.push(Line::default());
+        }
+        let status = match exchange.status {
+            Some(status) => status.to_string(),
+            None if exchange.cached => "cached".to_string(),
+            None => "no response".to_string(),
+        };
+        let mut summary = format!(
+            "{} | {} | {} ms",
+            exchange.model, status, exchange.duration_ms
+        );
+        if let Some(usage) = &exchange.usage {
+            summary.push_str(&format!(
+                " | {} prompt + {} completion tokens",
+                usage.prompt_tokens, usage.completion_tokens
+            ));
+        }
+        if exchange.redacted > 0 {
+            summary.push_str(&format!(" | {} secrets redacted", exchange.redacted));
+        }
+        lines.push(Line::styled(
+            summary,
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        for message in &exchange.request.messages {
+            lines.push(Line::styled(format!("{}:", message.role), theme.help_key));
+            lines.extend(
+                message
+                    .content
+                    .lines()
+                    .map(|line| Line::raw(line.to_string())),
+            );
+        }
+        lines.push(Line::styled("response:", theme.help_key));
+        // Pretty print JSON bodies, which arrive on one long line.
+        let response = serde_json::from_str::<serde_json::Value>(&exchange.response)
+            .ok()
+            .and_then(|body| serde_json::to_string_pretty(&body).ok())
+            .unwrap_or_else(|| exchange.response.clone());
+        lines.extend(response.lines().map(|line| Line::raw(line.to_string())));
+    }
+
+    let block = Block::bordered()
+        .title("Recent requests, newest first (arrows and page keys scroll, any other key closes)")
+        .border_type(BorderType::Rounded);
+
+    Clear.render(area, buf);
+    Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll, 0))
+        .render(area, buf);
+}
+
+/// Renders the help overlay, listing every key binding.
+fn render_help(keymap: &Keymap, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    let modes: &[(Mode, &str)] = if keymap.vi {
+        &[(Mode::Insert, "Insert mode"), (Mode::Normal, "Normal mode")]
+    } else {
+        &[(Mode::Insert, "")]
+    };
+
+    let mut lines = Vec::new();
+    for (mode, heading) in modes {
+        if !heading.is_empty() {
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::styled(
+                *heading,
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+        }
+        for (action, keys) in keymap.bindings(*mode) {
+            let keys = keys
+                .iter()
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(Line::from(vec![
+                Span::styled(format!("{:<18}", keys), theme.help_key),
+                Span::raw(action.description()),
+            ]));
+        }
     }
+
+    let [area] = Layout::horizontal([Constraint::Percentage(70)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(lines.len() as u16 + 2)])
+        .flex(Flex::Center)
+        .areas(area);
+
+    let block = Block::bordered()
+        .title("Keys")
+        .border_type(BorderType::Rounded);
+
+    Clear.render(area, buf);
+    Paragraph::new(lines).block(block).render(area, buf);
+}
+
+/// Renders an editable pane, showing the cursor when focused and scrolling to keep it in view.
+fn render_text_buffer(
+    buffer: &TextBuffer,
+    focused: bool,
+    block: Block,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let inner = block.inner(area);
+    let (text, scroll) = if focused {
+        let (row, col) = buffer.cursor_position();
+        // The cursor marker itself takes up a cell, so leave room for it on the right.
+        let scroll_y = row.saturating_sub(inner.height.saturating_sub(1) as usize);
+        let scroll_x = col.saturating_sub(inner.width.saturating_sub(1) as usize);
+        (buffer.with_cursor('█'), (scroll_y as u16, scroll_x as u16))
+    } else {
+        (buffer.text().to_string(), (0, 0))
+    };
+
+    Paragraph::new(text)
+        .block(block)
+        .scroll(scroll)
+        .render(area, buf);
 }
@@ -0,0 +1,172 @@
+use crate::text::TextBuffer;
+
+/// The maximum number of snapshots kept in each direction.
+const MAX_HISTORY: usize = 200;
+
+/// The state of a single pane at a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The pane the snapshot belongs to.
+    pub pane: usize,
+    /// The pane's text and cursor position.
+    pub buffer: TextBuffer,
+}
+
+/// The kind of edit that produced a snapshot.
+///
+/// Consecutive edits of the same kind in the same pane are grouped, so undo removes a typed word
+/// rather than a single character.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditKind {
+    Insert,
+    Delete,
+    /// Pasted text. Never grouped.
+    Paste,
+    /// A wholesale replacement of the pane, e.g. by a new completion. Never grouped.
+    Replace,
+}
+
+/// Undo and redo history across the editable panes.
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// The pane and kind of the last recorded edit, used for grouping.
+    last_edit: Option<(usize, EditKind)>,
+}
+
+impl UndoStack {
+    /// Constructs a new, empty [`UndoStack`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the state of a pane before it is edited.
+    ///
+    /// Recording a new edit discards anything that could have been redone.
+    pub fn record(&mut self, pane: usize, buffer: &TextBuffer, kind: EditKind) {
+        let edit = (pane, kind);
+        let groupable = matches!(kind, EditKind::Insert | EditKind::Delete);
+        if groupable && self.last_edit == Some(edit) {
+            return;
+        }
+        self.last_edit = Some(edit);
+        self.redo.clear();
+        push_bounded(
+            &mut self.undo,
+            Snapshot {
+                pane,
+                buffer: buffer.clone(),
+            },
+        );
+    }
+
+    /// End the current group of edits, e.g. because the cursor moved.
+    pub fn break_group(&mut self) {
+        self.last_edit = None;
+    }
+
+    /// Restore the most recent snapshot.
+    ///
+    /// `restore` installs the snapshot and returns the state it replaced, which becomes
+    /// available to [`UndoStack::redo`]. Returns the pane that was restored, if any.
+    pub fn undo(&mut self, restore: impl FnOnce(Snapshot) -> Snapshot) -> Option<usize> {
+        let snapshot = self.undo.pop()?;
+        let pane = snapshot.pane;
+        push_bounded(&mut self.redo, restore(snapshot));
+        self.last_edit = None;
+        Some(pane)
+    }
+
+    /// Re-apply the most recently undone snapshot. See [`UndoStack::undo`].
+    pub fn redo(&mut self, restore: impl FnOnce(Snapshot) -> Snapshot) -> Option<usize> {
+        let snapshot = self.redo.pop()?;
+        let pane = snapshot.pane;
+        push_bounded(&mut self.undo, restore(snapshot));
+        self.last_edit = None;
+        Some(pane)
+    }
+}
+
+fn push_bounded(stack: &mut Vec<Snapshot>, snapshot: Snapshot) {
+    if stack.len() == MAX_HISTORY {
+        stack.remove(0);
+    }
+    stack.push(snapshot);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> TextBuffer {
+        let mut buffer = TextBuffer::new();
+        buffer.set_text(text);
+        buffer
+    }
+
+    /// Undo or redo against a single pane, returning its text afterwards.
+    fn apply(
+        pane: &mut TextBuffer,
+        step: impl FnOnce(&mut dyn FnMut(Snapshot) -> Snapshot) -> Option<usize>,
+    ) -> String {
+        step(&mut |snapshot| Snapshot {
+            pane: snapshot.pane,
+            buffer: std::mem::replace(pane, snapshot.buffer),
+        });
+        pane.text().to_string()
+    }
+
+    #[test]
+    fn groups_runs_of_the_same_edit() {
+        let mut stack = UndoStack::new();
+        let mut pane = buffer("");
+        for text in ["l", "ls", "ls "] {
+            stack.record(0, &pane, EditKind::Insert);
+            pane = buffer(text);
+        }
+        stack.record(0, &pane, EditKind::Delete);
+        pane = buffer("ls");
+        stack.break_group();
+        stack.record(0, &pane, EditKind::Insert);
+        pane = buffer("ls -l");
+
+        // The typing, the deletion and the typing after the break are undone one group at a time.
+        assert_eq!(apply(&mut pane, |f| stack.undo(f)), "ls");
+        assert_eq!(apply(&mut pane, |f| stack.undo(f)), "ls ");
+        assert_eq!(apply(&mut pane, |f| stack.undo(f)), "");
+        assert_eq!(stack.undo(|snapshot| snapshot), None);
+    }
+
+    #[test]
+    fn pastes_and_replacements_are_never_grouped() {
+        let mut stack = UndoStack::new();
+        let mut pane = buffer("");
+        for text in ["a", "ab"] {
+            stack.record(1, &pane, EditKind::Replace);
+            pane = buffer(text);
+        }
+        assert_eq!(apply(&mut pane, |f| stack.undo(f)), "a");
+    }
+
+    #[test]
+    fn redoes_in_reverse_until_a_new_edit() {
+        let mut stack = UndoStack::new();
+        let mut pane = buffer("");
+        for text in ["one", "two", "three"] {
+            stack.record(1, &pane, EditKind::Replace);
+            pane = buffer(text);
+        }
+        assert_eq!(apply(&mut pane, |f| stack.undo(f)), "two");
+        assert_eq!(apply(&mut pane, |f| stack.undo(f)), "one");
+        assert_eq!(apply(&mut pane, |f| stack.redo(f)), "two");
+        assert_eq!(apply(&mut pane, |f| stack.redo(f)), "three");
+        assert_eq!(stack.redo(|snapshot| snapshot), None);
+
+        assert_eq!(apply(&mut pane, |f| stack.undo(f)), "two");
+        stack.record(1, &pane, EditKind::Insert);
+        pane = buffer("two!");
+        assert_eq!(stack.redo(|snapshot| snapshot), None);
+        assert_eq!(apply(&mut pane, |f| stack.undo(f)), "two");
+    }
+}
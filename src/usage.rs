@@ -0,0 +1,469 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Tokens used by a request, as reported by the provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+}
+
+/// The price of a model, in dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Price {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl Price {
+    pub fn cost(&self, usage: Usage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt
+            + usage.completion_tokens as f64 * self.completion)
+            / 1_000_000.0
+    }
+}
+
+/// What to do once a budget has been spent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverBudget {
+    /// Keep sending requests, but warn in the status line.
+    #[default]
+    Warn,
+    /// Refuse to send any more requests.
+    Refuse,
+}
+
+/// Usage settings, from the `[usage]` section of the config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UsageConfig {
+    /// The most to spend in a day, in dollars.
+    pub daily_budget: Option<f64>,
+    /// The most to spend in a month, in dollars.
+    pub monthly_budget: Option<f64>,
+    pub over_budget: OverBudget,
+    /// Prices by model, on top of the built in ones.
+    pub prices: HashMap<String, Price>,
+}
+
+impl UsageConfig {
+    /// The price of a model, if it is known.
+    pub fn price(&self, model: &str) -> Option<Price> {
+        self.prices
+            .get(model)
+            .copied()
+            .or_else(|| builtin_price(model))
+    }
+}
+
+/// Prices for the default model, per OpenRouter's listing.
+fn builtin_price(model: &str) -> Option<Price> {
+    match model {
+        "google/gemini-2.5-flash" => Some(Price {
+            prompt: 0.30,
+            completion: 2.50,
+        }),
+        _ => None,
+    }
+}
+
+/// Tokens and cost added up over some period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Totals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// The cost in dollars. Models without a known price count as free.
+    pub cost: f64,
+}
+
+impl Totals {
+    fn add(&mut self, usage: Usage, cost: f64) {
+        self.requests += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.cost += cost;
+    }
+
+    fn merge(&mut self, other: &Totals) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+    }
+
+    pub fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Totals for each day, stored as TOML in the data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    /// Totals keyed by UTC date, e.g. `2025-01-31`.
+    #[serde(default)]
+    pub days: BTreeMap<String, Totals>,
+}
+
+impl Ledger {
+    /// Where the ledger is stored, e.g. `~/.local/share/uhh/usage.toml` on Linux.
+    pub fn path() -> Result<PathBuf> {
+        dirs::data_dir()
+            .map(|dir| dir.join("uhh").join("usage.toml"))
+            .ok_or_else(|| eyre!("Could not find a data directory for the usage ledger"))
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&contents).wrap_err_with(|| format!("Invalid usage in {}", path.display()))
+    }
+
+    /// Write the ledger, replacing the file in one step so that it is never seen half written.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("toml.tmp");
+        std::fs::write(&temp, toml::to_string_pretty(self)?)
+            .wrap_err_with(|| format!("Failed to write {}", temp.display()))?;
+        std::fs::rename(&temp, path).wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Change the ledger at `path`, holding a lock on it so that another uhh running at the same
+    /// time can't write its totals in between reading and writing ours.
+    pub fn update(path: &Path, change: impl FnOnce(&mut Ledger)) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let lock_path = path.with_extension("lock");
+        let lock = std::fs::File::create(&lock_path)
+            .wrap_err_with(|| format!("Failed to open {}", lock_path.display()))?;
+        lock.lock()
+            .wrap_err_with(|| format!("Failed to lock {}", lock_path.display()))?;
+        let mut ledger = Self::load_from(path)?;
+        change(&mut ledger);
+        ledger.save_to(path)
+    }
+
+    pub fn day(&self, date: &str) -> Totals {
+        self.days.get(date).copied().unwrap_or_default()
+    }
+
+    /// The totals for the month of `date`.
+    pub fn month(&self, date: &str) -> Totals {
+        let month = &date[..date.len().min(7)];
+        let mut totals = Totals::default();
+        for (_, day) in self.days.iter().filter(|(day, _)| day.starts_with(month)) {
+            totals.merge(day);
+        }
+        totals
+    }
+}
+
+/// Today's date in UTC, as `YYYY-MM-DD`.
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) date, after Howard Hinnant's
+/// `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[derive(Debug, Default)]
+struct MeterState {
+    session: Totals,
+    /// Whether a request in this session used a model without a known price.
+    unpriced: bool,
+    /// The budget that has been spent, as of the last check.
+    over_budget: Option<String>,
+    /// Why the ledger couldn't be read or written, as of the last attempt.
+    ledger_failure: Option<String>,
+}
+
+/// Tracks token usage and cost, and enforces the budgets.
+///
+/// Clones share the same session totals, so requests made from background tasks are counted.
+#[derive(Debug, Clone, Default)]
+pub struct Meter {
+    config: Arc<UsageConfig>,
+    /// Where daily totals are persisted, or `None` to keep them in memory only.
+    ledger_path: Option<PathBuf>,
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl Meter {
+    pub fn new(config: UsageConfig, ledger_path: Option<PathBuf>) -> Self {
+        Self {
+            config: Arc::new(config),
+            ledger_path,
+            state: Arc::default(),
+        }
+    }
+
+    /// The totals for this session.
+    pub fn session(&self) -> Totals {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).session
+    }
+
+    /// Whether some of the session's usage couldn't be priced.
+    pub fn has_unpriced(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .unpriced
+    }
+
+    /// Count a request's usage, adding it to today's totals on disk.
+    ///
+    /// The request has been paid for by now, so a ledger that can't be updated is reported by
+    /// [`Meter::budget_warning`] rather than failing it. The ledger is updated on a blocking
+    /// thread, as another uhh may hold its lock, and the app has to keep responding meanwhile.
+    pub async fn record(&self, model: &str, usage: Usage) {
+        let price = self.config.price(model);
+        let cost = price.map_or(0.0, |price| price.cost(usage));
+        {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.session.add(usage, cost);
+            state.unpriced |= price.is_none();
+        }
+
+        let Some(path) = self.ledger_path.clone() else {
+            return;
+        };
+        let meter = self.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            Ledger::update(&path, |ledger| {
+                ledger.days.entry(today()).or_default().add(usage, cost);
+            })
+            .and_then(|()| meter.over_budget())
+        })
+        .await
+        .unwrap_or_else(|e| Err(eyre!("Failed to update the ledger: {}", e)));
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(over_budget) => {
+                state.over_budget = over_budget;
+                state.ledger_failure = None;
+            }
+            Err(e) => state.ledger_failure = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// The budget that had been spent as of the last request, if any, or why usage couldn't be
+    /// tracked, for the status line.
+    pub fn budget_warning(&self) -> Option<String> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.over_budget.clone().or_else(|| {
+            state
+                .ledger_failure
+                .as_ref()
+                .map(|failure| format!("Usage isn't being tracked: {}", failure))
+        })
+    }
+
+    /// Describe any budget that has been spent, or `None` if there is still money left.
+    fn over_budget(&self) -> Result<Option<String>> {
+        let Some(path) = &self.ledger_path else {
+            return Ok(None);
+        };
+        if self.config.daily_budget.is_none() && self.config.monthly_budget.is_none() {
+            return Ok(None);
+        }
+        let ledger = Ledger::load_from(path)?;
+        let today = today();
+        let periods = [
+            ("daily", self.config.daily_budget, ledger.day(&today)),
+            ("monthly", self.config.monthly_budget, ledger.month(&today)),
+        ];
+        for (period, budget, spent) in periods {
+            if let Some(budget) = budget
+                && spent.cost >= budget
+            {
+                return Ok(Some(format!(
+                    "The {} budget of ${:.2} has been spent (${:.2})",
+                    period, budget, spent.cost
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Check the budgets before a request, failing if one has been spent and the config says to
+    /// refuse further requests.
+    ///
+    /// A ledger that can't be read doesn't stop requests, as budgets are a safeguard rather than
+    /// a gate, but is reported by [`Meter::budget_warning`].
+    pub fn check_budget(&self) -> Result<()> {
+        let over_budget = {
+            let result = self.over_budget();
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            match result {
+                Ok(over_budget) => {
+                    state.ledger_failure = None;
+                    state.over_budget.clone_from(&over_budget);
+                    over_budget
+                }
+                Err(e) => {
+                    state.ledger_failure = Some(format!("{:#}", e));
+                    None
+                }
+            }
+        };
+        match over_budget {
+            Some(message) if self.config.over_budget == OverBudget::Refuse => {
+                Err(eyre!("{}, refusing to send more requests", message))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20_088), (2024, 12, 31));
+    }
+
+    #[test]
+    fn totals_months_and_prices_tokens() {
+        let price = Price {
+            prompt: 1.0,
+            completion: 2.0,
+        };
+        let usage = Usage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 500_000,
+        };
+        assert_eq!(price.cost(usage), 2.0);
+
+        let mut ledger = Ledger::default();
+        for day in ["2025-01-30", "2025-01-31", "2025-02-01"] {
+            ledger.days.entry(day.into()).or_default().add(usage, 2.0);
+        }
+        assert_eq!(ledger.month("2025-01-15").cost, 4.0);
+        assert_eq!(ledger.month("2025-01-15").requests, 2);
+        assert_eq!(ledger.day("2025-02-01").tokens(), 1_500_000);
+    }
+
+    fn usage() -> Usage {
+        Usage {
+            prompt_tokens: 100,
+            completion_tokens: 20,
+        }
+    }
+
+    fn ledger_path(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("uhh-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("usage.toml")
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn sessions_share_the_ledger() {
+        let path = ledger_path("ledger-test");
+        let meters: Vec<Meter> = (0..4)
+            .map(|_| Meter::new(UsageConfig::default(), Some(path.clone())))
+            .collect();
+        futures::future::join_all(meters.iter().map(|meter| async move {
+            for _ in 0..10 {
+                meter.record("test/model", usage()).await;
+            }
+        }))
+        .await;
+        let ledger = Ledger::load_from(&path).unwrap();
+        assert_eq!(ledger.day(&today()).requests, 40);
+        assert_eq!(meters[0].session().requests, 10);
+        assert_eq!(meters[0].budget_warning(), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn locked_ledgers_dont_hold_up_the_app() {
+        let path = ledger_path("locked-ledger-test");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let lock = std::fs::File::create(path.with_extension("lock")).unwrap();
+        lock.lock().unwrap();
+
+        let meter = Meter::new(UsageConfig::default(), Some(path.clone()));
+        let recording = tokio::spawn({
+            let meter = meter.clone();
+            async move { meter.record("test/model", usage()).await }
+        });
+        // Other tasks still run while the ledger waits for the lock.
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!recording.is_finished());
+        assert_eq!(meter.session().requests, 1);
+
+        lock.unlock().unwrap();
+        recording.await.unwrap();
+        assert_eq!(Ledger::load_from(&path).unwrap().day(&today()).requests, 1);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn corrupt_ledgers_are_reported_rather_than_failing() {
+        let path = ledger_path("corrupt-ledger-test");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "days = 'oops").unwrap();
+        let meter = Meter::new(
+            UsageConfig {
+                daily_budget: Some(0.01),
+                over_budget: OverBudget::Refuse,
+                ..UsageConfig::default()
+            },
+            Some(path.clone()),
+        );
+
+        assert!(meter.check_budget().is_ok());
+        let warning = meter.budget_warning().unwrap();
+        assert!(
+            warning.starts_with("Usage isn't being tracked: Invalid usage in"),
+            "{}",
+            warning
+        );
+        meter.record("test/model", usage()).await;
+        assert_eq!(meter.session().tokens(), 120);
+        assert!(meter.budget_warning().is_some());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(meter.check_budget().is_ok());
+        assert_eq!(meter.budget_warning(), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
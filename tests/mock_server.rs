@@ -0,0 +1,258 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use uhh::app::{App, SafetyStatus};
+use uhh::cache::{Cache, CacheConfig};
+use uhh::event::{AppEvent, Event};
+use uhh::infer::InferenceEngine;
+use uhh::inputs::Inputs;
+use uhh::keymap::{Keymap, KeysConfig};
+use uhh::mock::{MockScript, MockServer};
+use uhh::shell::Shell;
+use uhh::theme::{Theme, ThemeName};
+use uhh::transport::HttpTransport;
+
+/// Start a mock server with the given script, returning a handle to it and its base URL.
+async fn serve(script: &str) -> (MockServer, String) {
+    let script: MockScript = toml::from_str(script).unwrap();
+    let server = MockServer::new(script);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/v1", listener.local_addr().unwrap());
+    tokio::spawn(server.clone().serve(listener));
+    (server, url)
+}
+
+fn engine(base_url: String) -> InferenceEngine {
+    let transport = HttpTransport::new(String::new(), base_url.clone())
+        .unwrap()
+        .with_retries(2, Duration::from_millis(10));
+    InferenceEngine::new(
+        String::new(),
+        base_url,
+        "google/gemini-2.5-flash".into(),
+        Inputs::default(),
+        Vec::new(),
+        Shell::Bash,
+    )
+    .unwrap()
+    .with_transport(Arc::new(transport))
+}
+
+#[tokio::test]
+async fn retries_rate_limits_and_server_errors() {
+    let (server, url) = serve(
+        r#"
+        [[response]]
+        status = 429
+        retry_after = 0
+        times = 1
+
+        [[response]]
+        status = 503
+        times = 1
+
+        [[response]]
+        content = "df -h"
+        "#,
+    )
+    .await;
+    let response = engine(url)
+        .imagine_command("show disk usage".into())
+        .await
+        .unwrap();
+
+    assert_eq!(response.choices[0].message.content, "df -h");
+    assert_eq!(server.requests(), 3);
+}
+
+#[tokio::test]
+async fn gives_up_after_the_last_retry() {
+    let (server, url) = serve(
+        r#"
+        [[response]]
+        status = 502
+        content = "upstream is down"
+        "#,
+    )
+    .await;
+    let error = engine(url)
+        .imagine_command("show disk usage".into())
+        .await
+        .unwrap_err();
+
+    assert!(error.to_string().contains("status 502"));
+    assert!(error.to_string().contains("upstream is down"));
+    assert_eq!(server.requests(), 3);
+}
+
+#[tokio::test]
+async fn does_not_retry_client_errors() {
+    let (server, url) = serve(
+        r#"
+        [[response]]
+        status = 401
+        content = "No auth credentials found"
+        "#,
+    )
+    .await;
+    let error = engine(url)
+        .imagine_command("show disk usage".into())
+        .await
+        .unwrap_err();
+
+    assert!(error.to_string().contains("status 401"));
+    assert_eq!(server.requests(), 1);
+}
+
+#[tokio::test]
+async fn does_not_retry_timeouts() {
+    let (server, url) = serve(
+        r#"
+        [[response]]
+        content = "df -h"
+        delay_ms = 1000
+        "#,
+    )
+    .await;
+    let transport = HttpTransport::new(String::new(), url.clone())
+        .unwrap()
+        .with_retries(2, Duration::from_millis(10))
+        .with_timeout(Duration::from_millis(100));
+    let error = engine(url)
+        .with_transport(Arc::new(transport))
+        .imagine_command("show disk usage".into())
+        .await
+        .unwrap_err();
+
+    assert!(format!("{:?}", error).contains("timed out"), "{:?}", error);
+    // The provider may have answered, and billed for, the request anyway.
+    assert_eq!(server.requests(), 1);
+}
+
+#[tokio::test]
+async fn rejects_malformed_and_streamed_payloads() {
+    let (_, url) = serve(
+        r#"
+        [[response]]
+        match = "truncated"
+        body = '{"choices": [{"message": '
+
+        [[response]]
+        match = "streamed"
+        content = "ls -l"
+        stream = true
+        "#,
+    )
+    .await;
+    let engine = engine(url);
+    for prompt in ["truncated", "streamed"] {
+        let error = engine.imagine_command(prompt.into()).await.unwrap_err();
+        assert!(error.to_string().contains("Invalid response"), "{}", prompt);
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn shows_loading_states_while_waiting() {
+    let (_, url) = serve(
+        r#"
+        [[response]]
+        system = "inspect this for safety"
+        content = "Y. Only lists files."
+        delay_ms = 100
+
+        [[response]]
+        content = "ls -l"
+        delay_ms = 100
+        "#,
+    )
+    .await;
+    let keymap = Keymap::from_config(&KeysConfig::default()).unwrap();
+    let mut app = App::new(
+        engine(url),
+        true,
+        false,
+        keymap,
+        Theme::load(ThemeName::Dark),
+    );
+    app.events
+        .send(AppEvent::RequestCompletion("list files".into()));
+
+    let mut saw_completion_loading = false;
+    let mut saw_safety_loading = false;
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while app.safety_check_text.is_empty() {
+            if let Event::App(event) = app.events.next().await.unwrap() {
+                app.handle_app_event(event).await.unwrap();
+            }
+            saw_completion_loading |= app.is_loading_completion;
+            saw_safety_loading |= app.is_loading_safety_check;
+        }
+    })
+    .await
+    .expect("the safety check never finished");
+
+    assert!(saw_completion_loading && saw_safety_loading);
+    assert_eq!(app.response.text(), "ls -l");
+    assert!(!app.is_loading_completion && !app.is_loading_safety_check);
+}
+
+#[tokio::test]
+async fn resubmitting_a_prompt_asks_the_model_again() {
+    let (server, url) = serve(
+        r#"
+        [[response]]
+        system = "inspect this for safety"
+        content = "Y. Only lists files."
+
+        [[response]]
+        content = "ls -l"
+        "#,
+    )
+    .await;
+    let dir = std::env::temp_dir().join(format!("uhh-resubmit-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let cache = Cache::new(Some(dir.clone()), &CacheConfig::default());
+    let app = || {
+        App::new(
+            engine(url.clone()).with_cache(cache.clone()),
+            true,
+            false,
+            Keymap::from_config(&KeysConfig::default()).unwrap(),
+            Theme::load(ThemeName::Dark),
+        )
+    };
+    async fn submit(app: &mut App, prompt: &str) {
+        app.events.send(AppEvent::RequestCompletion(prompt.into()));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Event::App(event) = app.events.next().await.unwrap() {
+                    app.handle_app_event(event).await.unwrap();
+                }
+                if !app.is_loading_completion
+                    && !app.is_loading_safety_check
+                    && app.safety_status != SafetyStatus::Unknown
+                {
+                    break;
+                }
+            }
+        })
+        .await
+        .expect("the safety check never finished");
+    }
+
+    let mut first = app();
+    submit(&mut first, "list files").await;
+    assert_eq!(server.requests(), 2);
+    // The same prompt again gets a new answer, where the safety check is still cached.
+    submit(&mut first, "list files").await;
+    assert!(!first.completion_cached);
+    assert_eq!(server.requests(), 3);
+
+    // Asking afresh still caches the answer for next time.
+    let mut second = app();
+    submit(&mut second, "list files").await;
+    assert!(second.completion_cached);
+    assert_eq!(server.requests(), 3);
+    std::fs::remove_dir_all(dir).unwrap();
+}
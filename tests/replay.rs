@@ -0,0 +1,121 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use uhh::app::{App, SafetyStatus};
+use uhh::event::{AppEvent, Event};
+use uhh::infer::InferenceEngine;
+use uhh::inputs::Inputs;
+use uhh::keymap::{Keymap, KeysConfig};
+use uhh::shell::Shell;
+use uhh::theme::{Theme, ThemeName};
+use uhh::transport::{Cassette, Recorder, Replayer, Transport};
+
+const CASSETTE: &str = "tests/cassettes/safety_flow.json";
+
+fn engine(transport: Arc<dyn Transport>) -> InferenceEngine {
+    InferenceEngine::new(
+        String::new(),
+        String::new(),
+        "google/gemini-2.5-flash".into(),
+        Inputs::default(),
+        Vec::new(),
+        Shell::Bash,
+    )
+    .unwrap()
+    .with_transport(transport)
+}
+
+fn app(cassette: &str) -> App {
+    let replayer = Replayer::load(Path::new(cassette)).unwrap();
+    let keymap = Keymap::from_config(&KeysConfig::default()).unwrap();
+    App::new(
+        engine(Arc::new(replayer)),
+        true,
+        false,
+        keymap,
+        Theme::load(ThemeName::Dark),
+    )
+}
+
+/// Handle app events until `done` holds, as the app's main loop would.
+async fn run_until(app: &mut App, done: impl Fn(&App) -> bool) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !done(app) {
+            if let Event::App(event) = app.events.next().await.unwrap() {
+                app.handle_app_event(event).await.unwrap();
+            }
+        }
+    })
+    .await
+    .expect("the app never reached the expected state");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn generates_and_approves_a_safe_command() {
+    let mut app = app(CASSETTE);
+    app.events.send(AppEvent::RequestCompletion(
+        "list the files here, biggest first".into(),
+    ));
+    run_until(&mut app, |app| app.safety_status != SafetyStatus::Unknown).await;
+
+    assert_eq!(app.response.text(), "ls -l --sort=size");
+    assert_eq!(app.safety_status, SafetyStatus::Safe);
+    assert!(app.safety_check_text.contains("only lists files"));
+    assert!(!app.is_loading_completion && !app.is_loading_safety_check);
+    assert_eq!(app.client.meter().session().tokens(), 288);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn flags_a_dangerous_command() {
+    let mut app = app(CASSETTE);
+    app.events.send(AppEvent::RequestCompletion(
+        "clear out this directory".into(),
+    ));
+    run_until(&mut app, |app| app.safety_status != SafetyStatus::Unknown).await;
+
+    assert_eq!(app.response.text(), "rm -rf ./*");
+    assert_eq!(app.safety_status, SafetyStatus::Unsafe);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn shows_api_errors_in_the_command_pane() {
+    let mut app = app(CASSETTE);
+    app.events
+        .send(AppEvent::RequestCompletion("show disk usage".into()));
+    run_until(&mut app, |app| !app.response.text().is_empty()).await;
+
+    assert!(app.response.text().contains("status 429"));
+    assert!(!app.is_loading_completion);
+    assert!(!app.is_loading_safety_check);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn recorded_cassettes_replay_the_same_responses() {
+    let path = std::env::temp_dir().join(format!("uhh-cassette-{}.json", std::process::id()));
+    let source = Arc::new(Replayer::load(Path::new(CASSETTE)).unwrap());
+    let recording = engine(Arc::new(Recorder::new(source, path.clone())));
+    let recorded = recording
+        .inspect_command("ls -l --sort=size".into())
+        .await
+        .unwrap();
+
+    assert_eq!(Cassette::load(&path).unwrap().interactions.len(), 1);
+    let replaying = engine(Arc::new(Replayer::load(&path).unwrap()));
+    let replayed = replaying
+        .inspect_command("ls -l --sort=size".into())
+        .await
+        .unwrap();
+    assert_eq!(
+        recorded.choices[0].message.content,
+        replayed.choices[0].message.content
+    );
+    // Each interaction is only replayed once.
+    assert!(
+        replaying
+            .inspect_command("ls -l --sort=size".into())
+            .await
+            .is_err()
+    );
+    std::fs::remove_file(path).unwrap();
+}
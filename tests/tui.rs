@@ -0,0 +1,301 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use expect_test::{ExpectFile, expect_file};
+use ratatui::Terminal;
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{Event as CrosstermEvent, KeyEvent};
+use uhh::app::{App, LinePrompt, SafetyStatus};
+use uhh::event::{AppEvent, Event, EventHandler};
+use uhh::infer::InferenceEngine;
+use uhh::inputs::{Input, InputKind, Inputs};
+use uhh::keymap::{KeyBinding, Keymap, KeysConfig};
+use uhh::shell::Shell;
+use uhh::theme::{Theme, ThemeName};
+use uhh::transport::Replayer;
+
+const CASSETTE: &str = "tests/cassettes/safety_flow.json";
+
+/// Drives an [`App`] with scripted events, rendering it to an in-memory terminal.
+///
+/// Run with `UPDATE_EXPECT=1` to rewrite the snapshots after an intended change to the UI.
+struct Harness {
+    app: App,
+    terminal: Terminal<TestBackend>,
+}
+
+impl Harness {
+    fn new(keys: KeysConfig) -> Self {
+        Self::with_inputs(keys, Inputs::default())
+    }
+
+    fn with_inputs(keys: KeysConfig, inputs: Inputs) -> Self {
+        let engine = InferenceEngine::new(
+            String::new(),
+            String::new(),
+            "google/gemini-2.5-flash".into(),
+            inputs,
+            Vec::new(),
+            Shell::Bash,
+        )
+        .unwrap()
+        .with_transport(Arc::new(Replayer::load(Path::new(CASSETTE)).unwrap()));
+        let app = App::new(
+            engine,
+            true,
+            false,
+            Keymap::from_config(&keys).unwrap(),
+            Theme::load(ThemeName::Dark),
+        )
+        .with_events(EventHandler::headless());
+        Self {
+            app,
+            terminal: Terminal::new(TestBackend::new(80, 20)).unwrap(),
+        }
+    }
+
+    /// Press the keys for a binding such as `ctrl+s`, handling whatever they queue.
+    async fn press(&mut self, binding: &str) {
+        let binding: KeyBinding = binding.parse().unwrap();
+        let key = KeyEvent::new(binding.code, binding.modifiers);
+        self.queue(Event::Crossterm(CrosstermEvent::Key(key)));
+        self.settle().await;
+    }
+
+    /// Type text a character at a time.
+    async fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            let key = KeyEvent::from(ratatui::crossterm::event::KeyCode::Char(c));
+            self.queue(Event::Crossterm(CrosstermEvent::Key(key)));
+        }
+        self.settle().await;
+    }
+
+    async fn send(&mut self, event: AppEvent) {
+        self.queue(Event::App(event));
+        self.settle().await;
+    }
+
+    fn queue(&mut self, event: Event) {
+        self.app.events.sender.send(event).unwrap();
+    }
+
+    /// Handle every queued event, without waiting for requests in flight.
+    async fn settle(&mut self) {
+        while let Some(event) = self.app.events.try_next() {
+            self.app.handle_event(event).await.unwrap();
+        }
+    }
+
+    /// Handle events, including responses to requests in flight, until `done` holds.
+    async fn wait_until(&mut self, done: impl Fn(&App) -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !done(&self.app) {
+                let event = self.app.events.next().await.unwrap();
+                self.app.handle_event(event).await.unwrap();
+            }
+        })
+        .await
+        .expect("the app never reached the expected state");
+    }
+
+    /// Render the app and compare the screen to a snapshot.
+    fn assert_screen(&mut self, expected: ExpectFile) {
+        let frame = self
+            .terminal
+            .draw(|frame| frame.render_widget(&self.app, frame.area()))
+            .unwrap();
+        let buffer = frame.buffer;
+        let mut screen = String::new();
+        for y in 0..buffer.area.height {
+            let line: String = (0..buffer.area.width)
+                .map(|x| buffer[(x, y)].symbol())
+                .collect();
+            screen.push_str(line.trim_end());
+            screen.push('\n');
+        }
+        expected.assert_eq(&screen);
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn generating_a_safe_command() {
+    let mut harness = Harness::new(KeysConfig::default());
+    harness.assert_screen(expect_file!["snapshots/empty.txt"]);
+
+    harness
+        .type_text("list the files here, biggest first")
+        .await;
+    harness.assert_screen(expect_file!["snapshots/typed.txt"]);
+
+    // Submitting with enter would offer matching snippets from the user's own library first, so
+    // ask the model directly.
+    let prompt = harness.app.input.text().to_string();
+    harness.send(AppEvent::RequestCompletion(prompt)).await;
+    harness.assert_screen(expect_file!["snapshots/loading.txt"]);
+
+    harness.wait_until(|app| !app.is_loading_completion).await;
+    harness.assert_screen(expect_file!["snapshots/checking.txt"]);
+
+    harness
+        .wait_until(|app| app.safety_status != SafetyStatus::Unknown)
+        .await;
+    harness.assert_screen(expect_file!["snapshots/safe.txt"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn flagging_a_dangerous_command() {
+    let mut harness = Harness::new(KeysConfig::default());
+    harness
+        .send(AppEvent::RequestCompletion(
+            "clear out this directory".into(),
+        ))
+        .await;
+    harness
+        .wait_until(|app| app.safety_status != SafetyStatus::Unknown)
+        .await;
+    harness.assert_screen(expect_file!["snapshots/unsafe.txt"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn showing_an_api_error() {
+    let mut harness = Harness::new(KeysConfig::default());
+    harness
+        .send(AppEvent::RequestCompletion("show disk usage".into()))
+        .await;
+    harness.wait_until(|app| !app.is_loading_completion).await;
+    harness.assert_screen(expect_file!["snapshots/error.txt"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn overlays() {
+    let mut harness = Harness::new(KeysConfig::default());
+    harness.press("f1").await;
+    harness.assert_screen(expect_file!["snapshots/help.txt"]);
+    harness.press("f1").await;
+
+    harness.press("ctrl+s").await;
+    harness.assert_screen(expect_file!["snapshots/nothing_to_save.txt"]);
+
+    harness
+        .send(AppEvent::CompletionResponse {
+            content: "cp {{source:path}} {{destination:path=backup/}}".into(),
+            cached: false,
+        })
+        .await;
+    harness.press("ctrl+s").await;
+    harness.type_text("copy #files").await;
+    harness.assert_screen(expect_file!["snapshots/save_prompt.txt"]);
+    harness.press("esc").await;
+
+    harness.press("down").await;
+    harness.press("enter").await;
+    harness.type_text("notes.txt").await;
+    harness.assert_screen(expect_file!["snapshots/form.txt"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn question_mark_shows_help_until_there_is_text() {
+    let mut harness = Harness::new(KeysConfig::default());
+    harness.type_text("?").await;
+    assert!(harness.app.show_help);
+    harness.type_text("?").await;
+    assert!(!harness.app.show_help);
+
+    harness.type_text("why?").await;
+    assert!(!harness.app.show_help);
+    assert_eq!(harness.app.input.text(), "why?");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn vi_normal_mode() {
+    let mut harness = Harness::new(KeysConfig {
+        vi: true,
+        ..KeysConfig::default()
+    });
+    harness.type_text("find large files").await;
+    harness.press("esc").await;
+    harness.assert_screen(expect_file!["snapshots/vi_normal.txt"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn listing_inputs_in_a_side_panel() {
+    let inputs = Inputs::resolve(&[
+        "tests/fixtures/photos/*.jpg".into(),
+        "tests/fixtures/photos".into(),
+        "captions.txt".into(),
+    ])
+    .unwrap();
+    let mut harness = Harness::with_inputs(KeysConfig::default(), inputs);
+    harness.type_text("shrink these to 800px wide").await;
+    harness.assert_screen(expect_file!["snapshots/inputs.txt"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn counting_inputs_that_dont_fit() {
+    let inputs = Inputs {
+        inputs: vec![Input {
+            given: "clips/*.mov".into(),
+            kind: InputKind::Glob,
+            files: (1..=40)
+                .map(|i| PathBuf::from(format!("clips/{:02}.mov", i)))
+                .collect(),
+        }],
+    };
+    let mut harness = Harness::with_inputs(KeysConfig::default(), inputs);
+    harness.assert_screen(expect_file!["snapshots/inputs_overflow.txt"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn undoing_a_command_drops_its_review() {
+    let mut harness = Harness::new(KeysConfig::default());
+    harness
+        .send(AppEvent::CompletionResponse {
+            content: "ls -l".into(),
+            cached: false,
+        })
+        .await;
+    harness
+        .send(AppEvent::SafetyCheckResponse {
+            content: "Y. Only lists files.".into(),
+            cached: false,
+        })
+        .await;
+    assert_eq!(harness.app.safety_status, SafetyStatus::Safe);
+
+    harness.press("ctrl+z").await;
+    assert_eq!(harness.app.response.text(), "");
+    assert_eq!(harness.app.safety_status, SafetyStatus::Unknown);
+    assert!(harness.app.safety_check_text.is_empty());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn saving_a_script_over_a_file_asks_first() {
+    let existing = std::env::temp_dir().join(format!("uhh-tui-script-{}.sh", std::process::id()));
+    std::fs::write(&existing, "echo keep me\n").unwrap();
+
+    let mut harness = Harness::new(KeysConfig::default());
+    harness.app.script_mode = true;
+    harness.app.response.set_text("#!/bin/bash\necho hello\n");
+    harness.app.focused_pane = 1;
+    harness.press("enter").await;
+    for _ in "script.sh".chars() {
+        harness.press("backspace").await;
+    }
+    harness.type_text(&existing.display().to_string()).await;
+    harness.press("enter").await;
+
+    let (prompt, path) = harness.app.line_prompt.as_ref().unwrap();
+    assert_eq!(*prompt, LinePrompt::OverwriteScript);
+    assert_eq!(path.text(), existing.display().to_string());
+    assert_eq!(
+        std::fs::read_to_string(&existing).unwrap(),
+        "echo keep me\n"
+    );
+
+    harness.press("backspace").await;
+    let (prompt, _) = harness.app.line_prompt.as_ref().unwrap();
+    assert_eq!(*prompt, LinePrompt::SaveScript);
+    std::fs::remove_file(existing).unwrap();
+}